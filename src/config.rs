@@ -2,9 +2,12 @@ use std::time::Duration;
 
 use derive_builder::Builder;
 
+use crate::{SamplingConfig, SeverityMapping};
+
 const MAX_BATCH: usize = 10;
 const BUFFER_SIZE: usize = 1_000;
 const MAX_DELAY: Duration = Duration::from_secs(2);
+const FATAL_ERROR_COOLDOWN: Duration = Duration::from_secs(60);
 
 #[derive(Debug, Clone, Builder)]
 #[builder(pattern = "owned", setter(into, strip_option))]
@@ -15,6 +18,22 @@ pub struct GoogleWriterConfig {
     pub max_delay: Duration,
     #[builder(default = BUFFER_SIZE)]
     pub buffer_size: usize,
+    /// Attach the event callsite (file, line, function) as the entry's `sourceLocation`.
+    #[builder(default = true)]
+    pub source_location: bool,
+    /// Prefixes stripped from source file paths, e.g. the build machine's checkout directory.
+    #[builder(default)]
+    pub source_path_prefixes: Vec<String>,
+    /// How event levels map onto Cloud Logging severities.
+    #[builder(default)]
+    pub severity_mapping: SeverityMapping,
+    /// Sampling and rate limiting of events, applied before they are queued.
+    #[builder(default)]
+    pub sampling: Option<SamplingConfig>,
+    /// How long entries are dropped after a fatal error (e.g. a revoked key) before
+    /// writing is tried again; doubled after each further fatal error, up to an hour.
+    #[builder(default = FATAL_ERROR_COOLDOWN)]
+    pub fatal_error_cooldown: Duration,
 }
 
 impl Default for GoogleWriterConfig {
@@ -23,6 +42,11 @@ impl Default for GoogleWriterConfig {
             max_batch: MAX_BATCH,
             max_delay: MAX_DELAY,
             buffer_size: BUFFER_SIZE,
+            source_location: true,
+            source_path_prefixes: Vec::new(),
+            severity_mapping: SeverityMapping::default(),
+            sampling: None,
+            fatal_error_cooldown: FATAL_ERROR_COOLDOWN,
         }
     }
 }
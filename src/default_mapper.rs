@@ -1,35 +1,328 @@
+use std::collections::BTreeMap;
+
 use serde_json::{Value, json};
 
 use crate::{
-    extract_trace_id, get_severity,
+    ErrorReporting, extract_span_id, extract_trace_id, extract_trace_sampled, get_severity,
     google_logger::{LogContext, LogMapper},
-    log_entry::{Labels, Resource},
+    log_entry::{Resource, limit_labels},
+    trace_resource_name,
+    utils::{
+        INSERT_ID_KEY, LABELS_KEY, REPORT_LOCATION_KEY, SEVERITY_FIELD, SOURCE_LOCATION_KEY,
+        SPAN_ID_KEY, TRACE_KEY, TRACE_SAMPLED_KEY, extract_field, value_to_string,
+    },
 };
 
-#[derive(Clone, Default)]
-pub struct DefaultLogMapper;
+/// The [`LogMapper`] used unless another one is configured.
+///
+/// Promotes the trace context, `insertId`, `sourceLocation`, labels (and, with the `http`
+/// feature, `httpRequest`) out of the payload into their `LogEntry` fields.
+#[derive(Debug, Clone, Default)]
+pub struct DefaultLogMapper {
+    error_reporting: Option<ErrorReporting>,
+}
+
+impl DefaultLogMapper {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Shapes error entries so that Cloud Error Reporting picks them up.
+    pub fn with_error_reporting(mut self, error_reporting: ErrorReporting) -> Self {
+        self.error_reporting = Some(error_reporting);
+        self
+    }
+}
 
 impl LogMapper for DefaultLogMapper {
-    fn map(&self, context: LogContext, log_entry: Value) -> Value {
+    fn map(&self, context: LogContext, mut log_entry: Value) -> Value {
         let log_name = format!("projects/{}/logs/{}", context.project_id, context.log_label);
 
-        let trace_id =
-            extract_trace_id(&log_entry).unwrap_or_else(|| json!("trace_id is undefined"));
+        let trace_id = extract_trace_id(&log_entry);
+        let trace = trace_id
+            .as_ref()
+            .map(|trace_id| trace_resource_name(&context.project_id, trace_id))
+            .unwrap_or_else(|| json!("trace_id is undefined"));
+        let trace_id = trace_id.unwrap_or_else(|| json!("trace_id is undefined"));
+        let span_id = extract_span_id(&log_entry);
+        let trace_sampled = extract_trace_sampled(&log_entry).filter(Value::is_boolean);
+        if let Some(entry) = log_entry.as_object_mut() {
+            for key in [TRACE_KEY, SPAN_ID_KEY, TRACE_SAMPLED_KEY] {
+                entry.remove(key);
+            }
+        }
+        let insert_id = log_entry
+            .as_object_mut()
+            .and_then(|entry| entry.remove(INSERT_ID_KEY));
+        let source_location = log_entry
+            .as_object_mut()
+            .and_then(|entry| entry.remove(SOURCE_LOCATION_KEY));
+        let report_location = log_entry
+            .as_object_mut()
+            .and_then(|entry| entry.remove(REPORT_LOCATION_KEY));
+        #[cfg(feature = "http")]
+        let http_request = crate::extract_http_request(&mut log_entry);
+        let labels = labels(&context, &mut log_entry, &trace_id);
+        if let Some(error_reporting) = &self.error_reporting {
+            let location = source_location.as_ref().or(report_location.as_ref());
+            error_reporting.apply(&mut log_entry, location);
+        }
+        // the resolved severity (or override) is a `LogEntry` field, not part of the payload
+        let severity = get_severity(&log_entry);
+        if let Some(entry) = log_entry.as_object_mut() {
+            entry.remove(SEVERITY_FIELD);
+        }
 
-        json!({
+        let mut mapped = json!({
             "log_name": log_name,
-            "resource": Resource::new_global(context.project_id.to_string()),
-            "severity": get_severity(&log_entry),
+            "resource": context
+                .resource
+                .clone()
+                .unwrap_or_else(|| Resource::new_global(context.project_id.to_string())),
+            "severity": severity,
             "timestamp": log_entry
                 .get("time")
                 .cloned()
                 .unwrap_or_else(|| json!(chrono::Utc::now().to_rfc3339())),
             "json_payload": log_entry,
-            "trace": trace_id.clone(),
-            "labels": Labels {
-                context: context.log_label.to_string(),
-                request_id: trace_id,
+            "trace": trace,
+            "labels": labels,
+        });
+
+        if let Some(span_id) = span_id {
+            mapped["span_id"] = span_id;
+        }
+        if let Some(trace_sampled) = trace_sampled {
+            mapped["trace_sampled"] = trace_sampled;
+        }
+        if let Some(insert_id) = insert_id {
+            mapped["insert_id"] = insert_id;
+        }
+        if let Some(source_location) = source_location {
+            mapped["source_location"] = source_location;
+        }
+        #[cfg(feature = "http")]
+        if let Some(http_request) = http_request {
+            mapped["http_request"] = http_request;
+        }
+
+        mapped
+    }
+}
+
+/// Collects the entry labels, from lowest to highest precedence: the built-in `context` and
+/// `requestId`, the static labels, fields promoted to labels and per-event `labels.*` fields.
+fn labels(
+    context: &LogContext,
+    log_entry: &mut Value,
+    trace_id: &Value,
+) -> BTreeMap<String, String> {
+    let mut labels = vec![
+        ("context".to_owned(), context.log_label.to_string()),
+        ("requestId".to_owned(), value_to_string(trace_id)),
+    ];
+
+    labels.extend(
+        context
+            .labels
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone())),
+    );
+
+    labels.extend(context.fields_as_labels.iter().filter_map(|field| {
+        extract_field(log_entry, field).map(|value| (field.clone(), value_to_string(&value)))
+    }));
+
+    if let Some(Value::Object(event_labels)) = log_entry
+        .as_object_mut()
+        .and_then(|entry| entry.remove(LABELS_KEY))
+    {
+        labels.extend(
+            event_labels
+                .into_iter()
+                .map(|(key, value)| (key, value_to_string(&value))),
+        );
+    }
+
+    // later labels win, and are the last to be dropped past 64 labels
+    limit_labels(labels)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn context() -> LogContext {
+        LogContext {
+            log_label: Arc::from("test-log"),
+            project_id: Arc::from("test-project"),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_insert_id_is_moved_out_of_payload() {
+        let entry = json!({
+            "message": "hello",
+            "logging.googleapis.com/insertId": "abc-0000000000000001",
+        });
+
+        let mapped = DefaultLogMapper::default().map(context(), entry);
+
+        assert_eq!(mapped["insert_id"], "abc-0000000000000001");
+        assert!(mapped["json_payload"].get(INSERT_ID_KEY).is_none());
+    }
+
+    #[test]
+    fn test_severity_is_moved_out_of_payload() {
+        let entry = json!({ "message": "disk full", "severity": "CRITICAL" });
+
+        let mapped = DefaultLogMapper::default().map(context(), entry);
+
+        assert_eq!(mapped["severity"], "CRITICAL");
+        assert!(mapped["json_payload"].get(SEVERITY_FIELD).is_none());
+    }
+
+    #[test]
+    fn test_insert_id_is_omitted_when_missing() {
+        let mapped = DefaultLogMapper::default().map(context(), json!({ "message": "hello" }));
+
+        assert!(mapped.get("insert_id").is_none());
+    }
+
+    #[test]
+    fn test_trace_context_is_promoted() {
+        let entry = json!({
+            "message": "hello",
+            "spans": [{
+                "name": "trace_context",
+                "trace_id": "0af7651916cd43dd8448eb211c80319c",
+                "span_id": "b7ad6b7169203331",
+                "trace_sampled": true,
+            }],
+        });
+
+        let mapped = DefaultLogMapper::default().map(context(), entry);
+
+        assert_eq!(
+            mapped["trace"],
+            "projects/test-project/traces/0af7651916cd43dd8448eb211c80319c"
+        );
+        assert_eq!(mapped["span_id"], "b7ad6b7169203331");
+        assert_eq!(mapped["trace_sampled"], true);
+        assert_eq!(
+            mapped["labels"]["requestId"],
+            "0af7651916cd43dd8448eb211c80319c"
+        );
+    }
+
+    #[test]
+    fn test_labels() {
+        let context = LogContext {
+            labels: Arc::new(BTreeMap::from([
+                ("env".to_owned(), "prod".to_owned()),
+                ("region".to_owned(), "europe-west1".to_owned()),
+            ])),
+            fields_as_labels: Arc::from(["tenant_id".to_owned(), "shard".to_owned()]),
+            ..context()
+        };
+        let entry = json!({
+            "message": "hello",
+            "tenantId": 42,
+            "spans": [{ "name": "job", "shard": "b" }],
+            "logging.googleapis.com/labels": { "region": "us-east1" },
+        });
+
+        let mapped = DefaultLogMapper::default().map(context, entry);
+        let labels = &mapped["labels"];
+
+        assert_eq!(labels["context"], "test-log");
+        assert_eq!(labels["env"], "prod");
+        assert_eq!(labels["tenant_id"], "42");
+        assert_eq!(labels["shard"], "b");
+        assert_eq!(labels["region"], "us-east1");
+        assert!(mapped["json_payload"].get(LABELS_KEY).is_none());
+    }
+
+    #[test]
+    fn test_labels_limit_keeps_highest_precedence() {
+        // with `context` and `requestId`, the static labels alone fill the 64 labels
+        let context = LogContext {
+            labels: Arc::new(
+                (0..62)
+                    .map(|i| (format!("static{i:02}"), "value".to_owned()))
+                    .collect(),
+            ),
+            fields_as_labels: Arc::from(["tenant_id".to_owned()]),
+            ..context()
+        };
+        let entry = json!({
+            "message": "hello",
+            "tenantId": 42,
+            "logging.googleapis.com/labels": { "request": "abc" },
+        });
+
+        let mapped = DefaultLogMapper::default().map(context, entry);
+        let labels = mapped["labels"].as_object().unwrap();
+
+        assert_eq!(labels.len(), 64);
+        assert_eq!(labels["request"], "abc");
+        assert_eq!(labels["tenant_id"], "42");
+        assert_eq!(labels["static61"], "value");
+        // the lowest precedence labels are dropped first
+        assert!(!labels.contains_key("context"));
+        assert!(!labels.contains_key("requestId"));
+    }
+
+    #[test]
+    fn test_source_location_is_promoted() {
+        let entry = json!({
+            "message": "hello",
+            "logging.googleapis.com/sourceLocation": {
+                "file": "src/main.rs",
+                "line": "42",
+                "function": "my_app::handlers",
             },
-        })
+        });
+
+        let mapped = DefaultLogMapper::default().map(context(), entry);
+
+        assert_eq!(mapped["source_location"]["file"], "src/main.rs");
+        assert_eq!(mapped["source_location"]["line"], "42");
+        assert!(mapped["json_payload"].get(SOURCE_LOCATION_KEY).is_none());
+    }
+
+    #[test]
+    fn test_error_reporting() {
+        let mapper = DefaultLogMapper::new().with_error_reporting(ErrorReporting::new("my-app"));
+        let entry = json!({
+            "severity": "ERROR",
+            "message": "request failed",
+            "logging.googleapis.com/sourceLocation": { "file": "src/main.rs", "line": "7" },
+        });
+
+        let mapped = mapper.map(context(), entry);
+        let payload = &mapped["json_payload"];
+
+        assert_eq!(payload["serviceContext"]["service"], "my-app");
+        assert_eq!(payload["context"]["reportLocation"]["lineNumber"], 7);
+        assert_eq!(mapped["source_location"]["file"], "src/main.rs");
+
+        // with `source_location` disabled, the callsite is only used as the report location
+        let entry = json!({
+            "severity": "ERROR",
+            "message": "request failed",
+            "tracing_gcloud_layer/reportLocation": { "file": "src/main.rs", "line": "7" },
+        });
+        let mapped = mapper.map(context(), entry);
+
+        assert_eq!(
+            mapped["json_payload"]["context"]["reportLocation"]["filePath"],
+            "src/main.rs"
+        );
+        assert!(mapped.get("source_location").is_none());
+        assert!(mapped["json_payload"].get(REPORT_LOCATION_KEY).is_none());
     }
 }
@@ -0,0 +1,189 @@
+use std::{
+    backtrace::{Backtrace, BacktraceStatus},
+    cell::RefCell,
+    error::Error,
+    fmt,
+};
+
+use serde_json::{Map, Value, json};
+use tracing::{
+    Event, Subscriber,
+    field::{Field, Visit},
+};
+use tracing_subscriber::{Layer, layer::Context};
+
+use crate::{
+    error_reporting::ERROR_FIELD,
+    utils::{SEVERITY_FIELD, camel_case},
+};
+
+thread_local! {
+    /// What was recorded from the event being formatted on this thread.
+    static CAPTURED: RefCell<Captured> = const {
+        RefCell::new(Captured {
+            errors: Vec::new(),
+            severity: None,
+        })
+    };
+}
+
+#[derive(Default)]
+struct Captured {
+    errors: Vec<(&'static str, Value)>,
+    /// The raw `severity` field, which `tracing_stackdriver` turns into `DEFAULT` when it
+    /// is not a valid severity.
+    severity: Option<String>,
+}
+
+/// A [`Layer`] that records `dyn Error` fields as structured objects.
+///
+/// `tracing_stackdriver` only keeps the `Display` string of an error field. This layer runs
+/// right before it and records, for every `error = &err as &dyn Error` field:
+/// - `message`: the error's `Display` string,
+/// - `sources`: the `Display` strings of its [`source`](Error::source) chain,
+/// - `backtrace`: a backtrace of the logging call, when enabled with `RUST_BACKTRACE` or
+///   `RUST_LIB_BACKTRACE`.
+///
+/// The writer then swaps the string for that object. The first error of an event lands
+/// under the `error` key (unless a non-error field is already named `error`), so it can
+/// be queried as `jsonPayload.error.sources` in the Logs Explorer.
+///
+/// It also records the raw value of a `severity` override field, so an explicit
+/// `severity = "DEFAULT"` can be told apart from an invalid one.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ErrorCaptureLayer;
+
+impl<S: Subscriber> Layer<S> for ErrorCaptureLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = CaptureVisitor::default();
+        event.record(&mut visitor);
+
+        CAPTURED.with(|captured| *captured.borrow_mut() = visitor.0);
+    }
+}
+
+#[derive(Default)]
+struct CaptureVisitor(Captured);
+
+impl Visit for CaptureVisitor {
+    fn record_error(&mut self, field: &Field, value: &(dyn Error + 'static)) {
+        self.0.errors.push((field.name(), structured_error(value)));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == SEVERITY_FIELD {
+            self.0.severity = Some(value.to_owned());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == SEVERITY_FIELD {
+            self.0.severity = Some(format!("{value:?}"));
+        }
+    }
+}
+
+/// Serializes `error` as `{ "message", "sources", "backtrace"? }`.
+pub(crate) fn structured_error(error: &(dyn Error + 'static)) -> Value {
+    let mut sources = Vec::new();
+    let mut source = error.source();
+    while let Some(error) = source {
+        sources.push(error.to_string());
+        source = error.source();
+    }
+
+    let mut structured = json!({
+        "message": error.to_string(),
+        "sources": sources,
+    });
+    let backtrace = Backtrace::capture();
+    if backtrace.status() == BacktraceStatus::Captured {
+        structured["backtrace"] = backtrace.to_string().into();
+    }
+
+    structured
+}
+
+/// Replaces the error fields of a serialized entry with the errors captured for it, and
+/// returns its raw `severity` field, if any.
+pub(crate) fn apply_captured(entry: &mut Map<String, Value>) -> Option<String> {
+    let Captured { errors, severity } =
+        CAPTURED.with(|captured| std::mem::take(&mut *captured.borrow_mut()));
+
+    for (index, (name, error)) in errors.into_iter().enumerate() {
+        let key = camel_case(name);
+        if entry.remove(&key).is_none() {
+            // not an error of this entry
+            continue;
+        }
+
+        let key = match index {
+            0 if !entry.contains_key(ERROR_FIELD) => ERROR_FIELD.to_owned(),
+            _ => key,
+        };
+        entry.insert(key, error);
+    }
+
+    severity
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Failure(&'static str, Option<Box<Failure>>);
+
+    impl fmt::Display for Failure {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str(self.0)
+        }
+    }
+
+    impl Error for Failure {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            self.1.as_deref().map(|source| source as _)
+        }
+    }
+
+    #[test]
+    fn test_structured_error() {
+        let error = Failure(
+            "payment failed",
+            Some(Box::new(Failure(
+                "connection reset",
+                Some(Box::new(Failure("broken pipe", None))),
+            ))),
+        );
+
+        let structured = structured_error(&error);
+
+        assert_eq!(structured["message"], "payment failed");
+        assert_eq!(
+            structured["sources"],
+            json!(["connection reset", "broken pipe"])
+        );
+    }
+
+    #[test]
+    fn test_apply_captured() {
+        CAPTURED.with(|captured| {
+            *captured.borrow_mut() = Captured {
+                errors: vec![
+                    ("db_error", json!({ "message": "timeout", "sources": [] })),
+                    ("other_error", json!({ "message": "gone", "sources": [] })),
+                ],
+                severity: Some(String::from("DEFAULT")),
+            }
+        });
+        let mut entry =
+            json!({ "message": "query failed", "dbError": "timeout", "otherError": "gone" });
+
+        let severity = apply_captured(entry.as_object_mut().unwrap());
+
+        assert_eq!(severity.as_deref(), Some("DEFAULT"));
+        assert_eq!(entry["error"]["message"], "timeout");
+        assert!(entry.get("dbError").is_none());
+        assert_eq!(entry["otherError"]["message"], "gone");
+    }
+}
@@ -0,0 +1,236 @@
+use serde_json::{Map, Value, json};
+
+use crate::{LogSeverity, get_severity, utils::value_to_string};
+
+/// `@type` that makes Error Reporting pick an entry up.
+pub const REPORTED_ERROR_EVENT_TYPE: &str =
+    "type.googleapis.com/google.devtools.clouderrorreporting.v1beta1.ReportedErrorEvent";
+/// Event field holding an error, e.g. `tracing::error!(error = &err as &dyn Error, "...")`.
+pub(crate) const ERROR_FIELD: &str = "error";
+/// Event field holding a backtrace, e.g. `backtrace = %Backtrace::capture()`.
+const BACKTRACE_FIELD: &str = "backtrace";
+
+/// Shapes error entries as [`ReportedErrorEvent`]s, so that Cloud Error Reporting groups them.
+///
+/// Entries at `ERROR` severity or above, and entries carrying a `dyn Error` recorded by the
+/// [`ErrorCaptureLayer`](crate::ErrorCaptureLayer) (at any severity), get:
+/// - `@type` and `serviceContext` (service name and version),
+/// - a `message` made of the event message, the error with its source chain and the
+///   backtrace, if any,
+/// - `context.reportLocation`, taken from the entry's `sourceLocation`.
+///
+/// ```
+/// use tracing_gcloud_layer::{DefaultLogMapper, ErrorReporting};
+///
+/// let mapper = DefaultLogMapper::new()
+///     .with_error_reporting(ErrorReporting::new("checkout").version(env!("CARGO_PKG_VERSION")));
+/// ```
+///
+/// [`ReportedErrorEvent`]: https://cloud.google.com/error-reporting/docs/formatting-error-messages
+#[derive(Debug, Clone)]
+pub struct ErrorReporting {
+    service: String,
+    version: Option<String>,
+    min_severity: LogSeverity,
+}
+
+impl ErrorReporting {
+    /// Reports errors under the service name `service`.
+    pub fn new(service: impl Into<String>) -> Self {
+        Self {
+            service: service.into(),
+            version: None,
+            min_severity: LogSeverity::Error,
+        }
+    }
+
+    /// Sets the service version, so errors can be told apart per release.
+    pub fn version(mut self, version: impl Into<String>) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    /// Reports entries at `severity` or above, instead of `ERROR` and above.
+    pub fn min_severity(mut self, severity: LogSeverity) -> Self {
+        self.min_severity = severity;
+        self
+    }
+
+    /// Turns `log_entry` into a `ReportedErrorEvent` if it is an error.
+    ///
+    /// `source_location` is the entry's `LogEntrySourceLocation`, used as the report location.
+    pub(crate) fn apply(&self, log_entry: &mut Value, source_location: Option<&Value>) {
+        if !self.is_error(log_entry) {
+            return;
+        }
+        let Some(entry) = log_entry.as_object_mut() else {
+            return;
+        };
+
+        let message = error_message(entry);
+        entry.insert("message".to_owned(), message.into());
+        entry.insert("@type".to_owned(), REPORTED_ERROR_EVENT_TYPE.into());
+
+        let mut service_context = json!({ "service": self.service });
+        if let Some(version) = &self.version {
+            service_context["version"] = version.as_str().into();
+        }
+        entry.insert("serviceContext".to_owned(), service_context);
+
+        if let Some(report_location) = source_location.map(report_location) {
+            entry.insert(
+                "context".to_owned(),
+                json!({ "reportLocation": report_location }),
+            );
+        }
+    }
+
+    /// Whether the entry is severe enough, or carries a captured error. Any other `error`
+    /// field (`false`, `"none"`, ...) does not make an entry an error.
+    fn is_error(&self, log_entry: &Value) -> bool {
+        log_entry.get(ERROR_FIELD).is_some_and(is_captured_error)
+            || get_severity(log_entry)
+                .as_str()
+                .and_then(|severity| severity.parse::<LogSeverity>().ok())
+                .is_some_and(|severity| severity >= self.min_severity)
+    }
+}
+
+/// Whether `error` has the `{ message, sources }` shape of an error captured by the
+/// `ErrorCaptureLayer`.
+fn is_captured_error(error: &Value) -> bool {
+    error.get("message").is_some_and(Value::is_string)
+        && error.get("sources").is_some_and(Value::is_array)
+}
+
+/// Joins the event message, the error and the backtrace into the reported message.
+fn error_message(entry: &Map<String, Value>) -> String {
+    let mut message = entry
+        .get("message")
+        .map(value_to_string)
+        .unwrap_or_default();
+
+    let error = entry.get(ERROR_FIELD);
+    if let Some(error) = error {
+        if !message.is_empty() {
+            message.push_str(": ");
+        }
+        // errors captured by `ErrorCaptureLayer` are `{ message, sources, backtrace }`
+        match error.get("message") {
+            Some(error_message) => {
+                message.push_str(&value_to_string(error_message));
+                for source in error["sources"].as_array().into_iter().flatten() {
+                    message.push_str("\nCaused by: ");
+                    message.push_str(&value_to_string(source));
+                }
+            }
+            None => message.push_str(&value_to_string(error)),
+        }
+    }
+
+    let backtrace = entry
+        .get(BACKTRACE_FIELD)
+        .or_else(|| error.and_then(|error| error.get(BACKTRACE_FIELD)));
+    if let Some(backtrace) = backtrace {
+        message.push_str("\n\nstack backtrace:\n");
+        message.push_str(&value_to_string(backtrace));
+    }
+
+    message
+}
+
+/// Converts a `LogEntrySourceLocation` into an Error Reporting `SourceLocation`.
+fn report_location(source_location: &Value) -> Value {
+    let mut location = json!({
+        "filePath": source_location.get("file").cloned().unwrap_or_default(),
+        "functionName": source_location.get("function").cloned().unwrap_or_default(),
+    });
+    // `lineNumber` is an int32, unlike the int64 (string) `line` of the source location
+    if let Some(line) = source_location
+        .get("line")
+        .and_then(|line| line.as_str().and_then(|line| line.parse::<i32>().ok()))
+    {
+        location["lineNumber"] = line.into();
+    }
+    location
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_event_shape() {
+        let reporting = ErrorReporting::new("checkout").version("1.4.2");
+        let source_location = json!({ "file": "src/pay.rs", "line": "42", "function": "pay" });
+        let mut entry = json!({
+            "severity": "ERROR",
+            "message": "payment failed",
+            "error": "connection reset",
+            "backtrace": "0: pay::charge",
+        });
+
+        reporting.apply(&mut entry, Some(&source_location));
+
+        assert_eq!(entry["@type"], REPORTED_ERROR_EVENT_TYPE);
+        assert_eq!(entry["serviceContext"]["service"], "checkout");
+        assert_eq!(entry["serviceContext"]["version"], "1.4.2");
+        assert_eq!(
+            entry["message"],
+            "payment failed: connection reset\n\nstack backtrace:\n0: pay::charge"
+        );
+        assert_eq!(entry["context"]["reportLocation"]["filePath"], "src/pay.rs");
+        assert_eq!(entry["context"]["reportLocation"]["lineNumber"], 42);
+    }
+
+    #[test]
+    fn test_non_errors_are_untouched() {
+        let reporting = ErrorReporting::new("checkout");
+        let mut entry = json!({ "severity": "WARNING", "message": "slow" });
+
+        reporting.apply(&mut entry, None);
+
+        assert!(entry.get("@type").is_none());
+
+        // an `error` key alone does not make an error
+        for error in [json!(false), json!("none"), Value::Null, json!("timeout")] {
+            let mut entry = json!({ "severity": "INFO", "message": "retrying", "error": error });
+            reporting.apply(&mut entry, None);
+
+            assert!(entry.get("@type").is_none(), "error: {error}");
+            assert_eq!(entry["message"], "retrying");
+        }
+
+        // unless it is a captured `dyn Error`
+        let mut entry = json!({
+            "severity": "WARNING",
+            "message": "retrying",
+            "error": { "message": "timeout", "sources": [] },
+        });
+        reporting.apply(&mut entry, None);
+
+        assert_eq!(entry["@type"], REPORTED_ERROR_EVENT_TYPE);
+        assert_eq!(entry["message"], "retrying: timeout");
+    }
+
+    #[test]
+    fn test_structured_error_chain() {
+        let reporting = ErrorReporting::new("checkout");
+        let mut entry = json!({
+            "severity": "ERROR",
+            "message": "payment failed",
+            "error": {
+                "message": "charge failed",
+                "sources": ["connection reset", "broken pipe"],
+                "backtrace": "0: pay::charge",
+            },
+        });
+
+        reporting.apply(&mut entry, None);
+
+        assert_eq!(
+            entry["message"],
+            "payment failed: charge failed\nCaused by: connection reset\nCaused by: broken pipe\n\nstack backtrace:\n0: pay::charge"
+        );
+    }
+}
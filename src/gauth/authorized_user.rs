@@ -0,0 +1,77 @@
+use std::fmt;
+
+use reqwest::Client;
+use serde_derive::Deserialize;
+use zeroize::Zeroizing;
+
+use super::errors::Result;
+use super::jwt::Token;
+use super::token_response;
+use crate::redaction::MASK;
+
+/// OAuth 2.0 token endpoint used to refresh user credentials.
+fn default_token_uri() -> String {
+    String::from("https://oauth2.googleapis.com/token")
+}
+
+/// An `authorized_user` credential JSON, as written by `gcloud auth application-default login`.
+///
+/// The refresh token is exchanged for access tokens with the scopes granted at login, so
+/// the requested scopes are not sent.
+#[derive(Clone, Deserialize)]
+pub(crate) struct AuthorizedUser {
+    client_id: String,
+    client_secret: Zeroizing<String>,
+    refresh_token: Zeroizing<String>,
+    quota_project_id: Option<String>,
+    /// The user's email, which recent `gcloud` versions record.
+    #[serde(default)]
+    account: String,
+    /// Token endpoint, as accepted by the Google auth libraries.
+    #[serde(default = "default_token_uri")]
+    token_uri: String,
+}
+
+impl fmt::Debug for AuthorizedUser {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthorizedUser")
+            .field("client_id", &self.client_id)
+            .field("client_secret", &MASK)
+            .field("refresh_token", &MASK)
+            .field("quota_project_id", &self.quota_project_id)
+            .field("account", &self.account)
+            .field("token_uri", &self.token_uri)
+            .finish()
+    }
+}
+
+impl AuthorizedUser {
+    /// The quota project set with `gcloud auth application-default set-quota-project`.
+    pub fn project_id(&self) -> Option<&str> {
+        self.quota_project_id.as_deref()
+    }
+
+    /// The user's email, or the OAuth client when `gcloud` did not record it.
+    pub fn identity(&self) -> String {
+        if self.account.is_empty() {
+            format!("user of OAuth client {}", self.client_id)
+        } else {
+            self.account.clone()
+        }
+    }
+
+    pub async fn access_token(&self, http_client: &Client) -> Result<Token> {
+        let response = http_client
+            .post(&self.token_uri)
+            .form(&[
+                ("grant_type", "refresh_token"),
+                ("client_id", &self.client_id),
+                ("client_secret", &self.client_secret),
+                ("refresh_token", &self.refresh_token),
+            ])
+            .send()
+            .await?;
+
+        token_response(response).await
+    }
+}
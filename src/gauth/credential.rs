@@ -0,0 +1,100 @@
+use serde_derive::Deserialize;
+
+use super::authorized_user::AuthorizedUser;
+use super::external_account::ExternalAccount;
+use super::impersonation::ImpersonatedServiceAccount;
+use super::jwt::ServiceAccountKey;
+
+/// A credential JSON, told apart by its `type`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GAuthCredential {
+    /// A service account key, exchanged for tokens with a signed JWT.
+    ServiceAccount(ServiceAccountKey),
+    /// Another credential impersonating a service account, as written by
+    /// `gcloud auth application-default login --impersonate-service-account`.
+    ImpersonatedServiceAccount(ImpersonatedServiceAccount),
+    /// A Workload Identity Federation configuration, exchanged for tokens at the STS API.
+    ExternalAccount(ExternalAccount),
+    /// A user's refresh token, as written by `gcloud auth application-default login`.
+    AuthorizedUser(AuthorizedUser),
+}
+
+impl GAuthCredential {
+    pub fn from_bytes(bytes: &[u8]) -> serde_json::Result<Self> {
+        serde_json::from_slice(bytes)
+    }
+
+    /// The project the credential belongs to, if it names one.
+    pub fn project_id(&self) -> Option<&str> {
+        match self {
+            Self::ServiceAccount(key) => Some(&key.project_id),
+            Self::ImpersonatedServiceAccount(credential) => credential.project_id(),
+            Self::ExternalAccount(credential) => credential.project_id(),
+            Self::AuthorizedUser(credential) => credential.project_id(),
+        }
+    }
+
+    /// The id of the service account key, if the credential is one.
+    pub fn key_id(&self) -> Option<&str> {
+        match self {
+            Self::ServiceAccount(key) => Some(&key.private_key_id).filter(|id| !id.is_empty()),
+            _ => None,
+        }
+        .map(String::as_str)
+    }
+
+    /// Who the credential authenticates as, for logging.
+    pub fn identity(&self) -> String {
+        match self {
+            Self::ServiceAccount(key) => key.client_email.clone(),
+            Self::ImpersonatedServiceAccount(credential) => credential.identity().to_owned(),
+            Self::ExternalAccount(credential) => credential.identity(),
+            Self::AuthorizedUser(credential) => credential.identity(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_credential_type() {
+        let credential = GAuthCredential::from_bytes(include_bytes!(
+            "../../test_fixtures/service-account-key.json"
+        ))
+        .unwrap();
+        assert!(matches!(credential, GAuthCredential::ServiceAccount(_)));
+
+        let credential = GAuthCredential::from_bytes(
+            br#"{
+                "type": "external_account",
+                "audience": "//iam.googleapis.com/projects/123/locations/global/workloadIdentityPools/github/providers/actions",
+                "subject_token_type": "urn:ietf:params:oauth:token-type:jwt",
+                "token_url": "https://sts.googleapis.com/v1/token",
+                "credential_source": { "file": "/var/run/token" }
+            }"#,
+        )
+        .unwrap();
+        assert!(matches!(credential, GAuthCredential::ExternalAccount(_)));
+        assert_eq!(credential.project_id(), None);
+
+        let credential = GAuthCredential::from_bytes(
+            br#"{
+                "type": "authorized_user",
+                "client_id": "764086051850-6qr4p6gpi6hn506pt8ejuq83di341hur.apps.googleusercontent.com",
+                "client_secret": "secret",
+                "refresh_token": "refresh",
+                "quota_project_id": "my-project",
+                "account": "jane@example.com"
+            }"#,
+        )
+        .unwrap();
+        assert!(matches!(credential, GAuthCredential::AuthorizedUser(_)));
+        assert_eq!(credential.project_id(), Some("my-project"));
+        assert_eq!(credential.identity(), "jane@example.com");
+
+        assert!(GAuthCredential::from_bytes(br#"{ "type": "unknown" }"#).is_err());
+    }
+}
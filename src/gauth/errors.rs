@@ -13,6 +13,18 @@ pub enum GAuthError {
     #[error("failed to decode base64")]
     Base64Decode(#[from] base64::DecodeError),
 
+    #[error("no PEM private key block found")]
+    PemMissingBlock,
+
+    #[error("PEM block `{0}` has no END line")]
+    PemUnterminated(String),
+
+    #[error("unsupported PEM block `{0}`, expected `PRIVATE KEY` or `RSA PRIVATE KEY`")]
+    PemUnsupportedLabel(String),
+
+    #[error("encrypted private keys are not supported")]
+    PemEncrypted,
+
     #[error("failed to create rsa key pair: {0}")]
     RsaKeyPair(String),
 
@@ -22,8 +34,54 @@ pub enum GAuthError {
     #[error("failed to send request")]
     HttpReqwest(#[from] reqwest::Error),
 
+    #[error("invalid token response: {0}")]
+    InvalidResponse(String),
+
+    #[error(
+        "token endpoint returned {status} `{error}`{}",
+        description.as_deref().map(|description| format!(": {description}")).unwrap_or_default()
+    )]
+    TokenEndpoint {
+        /// HTTP status of the response.
+        status: u16,
+        /// OAuth error code (e.g. `invalid_grant`) or Google API status (e.g. `PERMISSION_DENIED`).
+        error: String,
+        description: Option<String>,
+    },
+
+    #[error("invalid credential configuration: {0}")]
+    InvalidConfig(String),
+
+    #[error("failed to get the subject token: {0}")]
+    SubjectToken(String),
+
     #[error("systemTime before UNIX EPOCH")]
     SystemTime(#[from] std::time::SystemTimeError),
 }
 
+impl GAuthError {
+    /// Whether fetching a token again may succeed: network errors, rate limiting, server
+    /// errors and signer or subject token failures.
+    ///
+    /// Other errors, like a revoked or malformed key (`invalid_grant` from the token
+    /// endpoint, which is also returned on clock skew), need a configuration change.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::TokenEndpoint { status, error, .. } => {
+                *status == 429 || *status >= 500 || error == "temporarily_unavailable"
+            }
+            Self::HttpReqwest(err) => err
+                .status()
+                .is_none_or(|status| status.as_u16() == 429 || status.is_server_error()),
+            Self::RsaSign(_) | Self::InvalidResponse(_) | Self::SubjectToken(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the token endpoint rejected the credential grant, e.g. a revoked key.
+    pub fn is_invalid_grant(&self) -> bool {
+        matches!(self, Self::TokenEndpoint { error, .. } if error == "invalid_grant")
+    }
+}
+
 pub type Result<T> = StdResult<T, GAuthError>;
@@ -0,0 +1,393 @@
+use std::{collections::BTreeMap, path::PathBuf, process::Stdio, time::Duration};
+
+use reqwest::Client;
+use serde_derive::Deserialize;
+use serde_json::Value;
+
+use super::CLOUD_PLATFORM_SCOPE;
+use super::errors::{GAuthError, Result};
+use super::impersonation::{
+    DEFAULT_LIFETIME, generate_access_token, impersonation_url_email, impersonation_url_project_id,
+};
+use super::jwt::Token;
+use super::token_response;
+use crate::utils::timestamp;
+
+const TOKEN_EXCHANGE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:token-exchange";
+const ACCESS_TOKEN_TYPE: &str = "urn:ietf:params:oauth:token-type:access_token";
+/// Executable sources only run when this environment variable is set to `1`.
+const ALLOW_EXECUTABLES: &str = "GOOGLE_EXTERNAL_ACCOUNT_ALLOW_EXECUTABLES";
+const DEFAULT_EXECUTABLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// An `external_account` ([Workload Identity Federation]) credential JSON.
+///
+/// A subject token from the configured source (a file, a URL or an executable) is
+/// exchanged for a federated access token at the STS API, which is then optionally
+/// exchanged for a service account token through `service_account_impersonation_url`.
+///
+/// [Workload Identity Federation]: https://cloud.google.com/iam/docs/workload-identity-federation
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct ExternalAccount {
+    audience: String,
+    subject_token_type: String,
+    token_url: String,
+    service_account_impersonation_url: Option<String>,
+    #[serde(default)]
+    service_account_impersonation: ServiceAccountImpersonation,
+    credential_source: CredentialSource,
+    quota_project_id: Option<String>,
+    workforce_pool_user_project: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct ServiceAccountImpersonation {
+    token_lifetime_seconds: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum CredentialSource {
+    /// Native AWS sources (`environment_id: aws1`) sign a `GetCallerIdentity` request,
+    /// which is not supported; use an executable source instead.
+    Aws {
+        environment_id: String,
+    },
+    Executable {
+        executable: ExecutableSource,
+    },
+    File {
+        file: PathBuf,
+        #[serde(default)]
+        format: Format,
+    },
+    Url {
+        url: String,
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+        #[serde(default)]
+        format: Format,
+    },
+}
+
+/// How a file or URL source returns the subject token.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Format {
+    /// The whole body is the token.
+    #[default]
+    Text,
+    /// The token is the `subject_token_field_name` field of a JSON body.
+    Json { subject_token_field_name: String },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ExecutableSource {
+    command: String,
+    timeout_millis: Option<u64>,
+    output_file: Option<PathBuf>,
+}
+
+/// The response of an executable source, printed to stdout or cached in `output_file`.
+#[derive(Debug, Deserialize)]
+struct ExecutableResponse {
+    success: bool,
+    id_token: Option<String>,
+    saml_response: Option<String>,
+    expiration_time: Option<u64>,
+    code: Option<String>,
+    message: Option<String>,
+}
+
+impl ExternalAccount {
+    /// The project of the impersonated service account, or the quota project.
+    pub fn project_id(&self) -> Option<&str> {
+        self.service_account_impersonation_url
+            .as_deref()
+            .and_then(impersonation_url_project_id)
+            .or(self.quota_project_id.as_deref())
+    }
+
+    /// The impersonated service account, or the workload identity pool provider.
+    pub fn identity(&self) -> String {
+        match self
+            .service_account_impersonation_url
+            .as_deref()
+            .and_then(impersonation_url_email)
+        {
+            Some(email) => email.to_owned(),
+            None => format!("federated principal of {}", self.audience),
+        }
+    }
+
+    /// Rejects credential sources this crate cannot read a subject token from.
+    pub fn validate(&self) -> Result<()> {
+        match &self.credential_source {
+            CredentialSource::Aws { environment_id } => Err(GAuthError::InvalidConfig(format!(
+                "unsupported credential source `{environment_id}`, use an executable source"
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    pub async fn access_token(&self, http_client: &Client, scopes: &str) -> Result<Token> {
+        let subject_token = self.subject_token(http_client).await?;
+
+        let Some(url) = &self.service_account_impersonation_url else {
+            return self
+                .exchange_token(http_client, &subject_token, scopes)
+                .await;
+        };
+
+        let federated_token = self
+            .exchange_token(http_client, &subject_token, CLOUD_PLATFORM_SCOPE)
+            .await?;
+        let lifetime = self
+            .service_account_impersonation
+            .token_lifetime_seconds
+            .map_or(DEFAULT_LIFETIME, Duration::from_secs);
+
+        generate_access_token(
+            http_client,
+            url,
+            &federated_token.bearer_token(),
+            &[],
+            scopes,
+            lifetime,
+        )
+        .await
+    }
+
+    /// Exchanges `subject_token` for a federated access token with `scopes` at the STS API.
+    async fn exchange_token(
+        &self,
+        http_client: &Client,
+        subject_token: &str,
+        scopes: &str,
+    ) -> Result<Token> {
+        let mut form = vec![
+            ("grant_type", TOKEN_EXCHANGE_GRANT_TYPE.to_owned()),
+            ("audience", self.audience.clone()),
+            ("scope", scopes.to_owned()),
+            ("requested_token_type", ACCESS_TOKEN_TYPE.to_owned()),
+            ("subject_token", subject_token.to_owned()),
+            ("subject_token_type", self.subject_token_type.clone()),
+        ];
+        // workforce pools bill the user project, unless a service account is impersonated
+        if let Some(user_project) = &self.workforce_pool_user_project
+            && self.service_account_impersonation_url.is_none()
+        {
+            form.push((
+                "options",
+                serde_json::json!({ "userProject": user_project }).to_string(),
+            ));
+        }
+
+        let response = http_client.post(&self.token_url).form(&form).send().await?;
+
+        token_response(response).await
+    }
+
+    async fn subject_token(&self, http_client: &Client) -> Result<String> {
+        match &self.credential_source {
+            CredentialSource::Aws { environment_id } => Err(GAuthError::SubjectToken(format!(
+                "unsupported credential source `{environment_id}`, use an executable source"
+            ))),
+            CredentialSource::File { file, format } => {
+                let body = std::fs::read_to_string(file).map_err(|err| {
+                    GAuthError::SubjectToken(format!("{err}: {}", file.display()))
+                })?;
+                format.subject_token(&body)
+            }
+            CredentialSource::Url {
+                url,
+                headers,
+                format,
+            } => {
+                let mut request = http_client.get(url);
+                for (name, value) in headers {
+                    request = request.header(name, value);
+                }
+                let body = request.send().await?.error_for_status()?.text().await?;
+                format.subject_token(&body)
+            }
+            CredentialSource::Executable { executable } => {
+                self.executable_subject_token(executable).await
+            }
+        }
+    }
+
+    /// Runs the executable source, unless `output_file` still holds an unexpired token.
+    async fn executable_subject_token(&self, executable: &ExecutableSource) -> Result<String> {
+        if std::env::var(ALLOW_EXECUTABLES).as_deref() != Ok("1") {
+            return Err(GAuthError::SubjectToken(format!(
+                "executable sources must be allowed with {ALLOW_EXECUTABLES}=1"
+            )));
+        }
+
+        if let Some(output_file) = &executable.output_file
+            && let Ok(cached) = std::fs::read(output_file)
+            && let Ok(response) = serde_json::from_slice::<ExecutableResponse>(&cached)
+            && response.expiration_time.is_some()
+            && let Ok(token) = response.subject_token(timestamp()?)
+        {
+            return Ok(token);
+        }
+
+        let mut args = executable.command.split_whitespace();
+        let program = args
+            .next()
+            .ok_or_else(|| GAuthError::SubjectToken("empty executable command".to_owned()))?;
+        let mut command = tokio::process::Command::new(program);
+        command
+            .args(args)
+            .env("GOOGLE_EXTERNAL_ACCOUNT_AUDIENCE", &self.audience)
+            .env(
+                "GOOGLE_EXTERNAL_ACCOUNT_TOKEN_TYPE",
+                &self.subject_token_type,
+            )
+            .env("GOOGLE_EXTERNAL_ACCOUNT_INTERACTIVE", "0")
+            .stdin(Stdio::null())
+            .kill_on_drop(true);
+        if let Some(email) = self
+            .service_account_impersonation_url
+            .as_deref()
+            .and_then(impersonation_url_email)
+        {
+            command.env("GOOGLE_EXTERNAL_ACCOUNT_IMPERSONATED_EMAIL", email);
+        }
+        if let Some(output_file) = &executable.output_file {
+            command.env("GOOGLE_EXTERNAL_ACCOUNT_OUTPUT_FILE", output_file);
+        }
+
+        let timeout = executable
+            .timeout_millis
+            .map_or(DEFAULT_EXECUTABLE_TIMEOUT, Duration::from_millis);
+        let output = tokio::time::timeout(timeout, command.output())
+            .await
+            .map_err(|_| GAuthError::SubjectToken(format!("`{}` timed out", executable.command)))?
+            .map_err(|err| GAuthError::SubjectToken(format!("`{}`: {err}", executable.command)))?;
+        if !output.status.success() {
+            return Err(GAuthError::SubjectToken(format!(
+                "`{}` exited with {}",
+                executable.command, output.status
+            )));
+        }
+
+        serde_json::from_slice::<ExecutableResponse>(&output.stdout)?.subject_token(timestamp()?)
+    }
+}
+
+impl Format {
+    fn subject_token(&self, body: &str) -> Result<String> {
+        match self {
+            Self::Text => Ok(body.trim().to_owned()),
+            Self::Json {
+                subject_token_field_name,
+            } => serde_json::from_str::<Value>(body)?
+                .get(subject_token_field_name)
+                .and_then(Value::as_str)
+                .map(str::to_owned)
+                .ok_or_else(|| {
+                    GAuthError::SubjectToken(format!("no `{subject_token_field_name}` field"))
+                }),
+        }
+    }
+}
+
+impl ExecutableResponse {
+    /// The token of a successful, unexpired response.
+    fn subject_token(self, now: u64) -> Result<String> {
+        if !self.success {
+            return Err(GAuthError::SubjectToken(format!(
+                "executable failed with {}: {}",
+                self.code.as_deref().unwrap_or("no code"),
+                self.message.as_deref().unwrap_or("no message"),
+            )));
+        }
+        if self
+            .expiration_time
+            .is_some_and(|expiration| expiration <= now)
+        {
+            return Err(GAuthError::SubjectToken(
+                "executable token expired".to_owned(),
+            ));
+        }
+
+        self.id_token
+            .or(self.saml_response)
+            .ok_or_else(|| GAuthError::SubjectToken("executable returned no token".to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_credential_source() {
+        let credential: ExternalAccount = serde_json::from_str(
+            r#"{
+                "type": "external_account",
+                "audience": "//iam.googleapis.com/projects/123/locations/global/workloadIdentityPools/github/providers/actions",
+                "subject_token_type": "urn:ietf:params:oauth:token-type:jwt",
+                "token_url": "https://sts.googleapis.com/v1/token",
+                "service_account_impersonation_url": "https://iamcredentials.googleapis.com/v1/projects/-/serviceAccounts/logger@my-project.iam.gserviceaccount.com:generateAccessToken",
+                "credential_source": {
+                    "url": "http://localhost:5000/token",
+                    "headers": { "Metadata": "True" },
+                    "format": { "type": "json", "subject_token_field_name": "value" }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(credential.project_id(), Some("my-project"));
+        let CredentialSource::Url { format, .. } = credential.credential_source else {
+            panic!("expected a url source");
+        };
+        assert_eq!(format.subject_token(r#"{"value":"abc"}"#).unwrap(), "abc");
+        assert!(format.subject_token(r#"{"other":"abc"}"#).is_err());
+        assert_eq!(Format::Text.subject_token("abc\n").unwrap(), "abc");
+    }
+
+    #[test]
+    fn test_aws_source_is_rejected() {
+        let credential: ExternalAccount = serde_json::from_str(
+            r#"{
+                "type": "external_account",
+                "audience": "//iam.googleapis.com/projects/123/locations/global/workloadIdentityPools/aws/providers/aws",
+                "subject_token_type": "urn:ietf:params:aws:token-type:aws4_request",
+                "token_url": "https://sts.googleapis.com/v1/token",
+                "credential_source": {
+                    "environment_id": "aws1",
+                    "region_url": "http://169.254.169.254/latest/meta-data/placement/availability-zone",
+                    "url": "http://169.254.169.254/latest/meta-data/iam/security-credentials",
+                    "regional_cred_verification_url": "https://sts.{region}.amazonaws.com?Action=GetCallerIdentity&Version=2011-06-15"
+                }
+            }"#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            credential.validate(),
+            Err(GAuthError::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_executable_response() {
+        let response = |json: &str| serde_json::from_str::<ExecutableResponse>(json).unwrap();
+
+        let token = response(
+            r#"{"version":1,"success":true,"token_type":"urn:ietf:params:oauth:token-type:id_token","id_token":"abc","expiration_time":200}"#,
+        );
+        assert_eq!(token.subject_token(100).unwrap(), "abc");
+
+        let expired =
+            response(r#"{"version":1,"success":true,"id_token":"abc","expiration_time":50}"#);
+        assert!(expired.subject_token(100).is_err());
+
+        let failed = response(r#"{"version":1,"success":false,"code":"401","message":"denied"}"#);
+        assert!(failed.subject_token(100).is_err());
+    }
+}
@@ -0,0 +1,242 @@
+use std::{fmt, time::Duration};
+
+use reqwest::Client;
+use serde_derive::Deserialize;
+use serde_json::json;
+
+use super::credential::GAuthCredential;
+use super::errors::{GAuthError, Result};
+use super::jwt::Token;
+use super::token_response;
+use crate::redaction::MASK;
+
+/// Lifetime of impersonated tokens unless configured otherwise (the API maximum
+/// without the `constraints/iam.allowServiceAccountCredentialLifetimeExtension` policy).
+pub(super) const DEFAULT_LIFETIME: Duration = Duration::from_secs(3600);
+
+/// Impersonates a service account through the IAM Credentials [`generateAccessToken`] API.
+///
+/// The configured credential only needs `roles/iam.serviceAccountTokenCreator` on the target
+/// service account (or on the last delegate), so logs can be written as a dedicated service
+/// account without distributing its key.
+///
+/// ```
+/// use std::time::Duration;
+/// use tracing_gcloud_layer::Impersonation;
+///
+/// let impersonation = Impersonation::new("logger@my-project.iam.gserviceaccount.com")
+///     .delegate("ci@my-project.iam.gserviceaccount.com")
+///     .lifetime(Duration::from_secs(900));
+/// ```
+///
+/// [`generateAccessToken`]: https://cloud.google.com/iam/docs/reference/credentials/rest/v1/projects.serviceAccounts/generateAccessToken
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Impersonation {
+    target_principal: String,
+    delegates: Vec<String>,
+    lifetime: Duration,
+}
+
+impl Impersonation {
+    /// Impersonates the service account with email `target_principal`.
+    pub fn new(target_principal: impl Into<String>) -> Self {
+        Self {
+            target_principal: target_principal.into(),
+            delegates: Vec::new(),
+            lifetime: DEFAULT_LIFETIME,
+        }
+    }
+
+    /// Appends a service account to the delegation chain, from the caller to the target.
+    pub fn delegate(mut self, delegate: impl Into<String>) -> Self {
+        self.delegates.push(delegate.into());
+        self
+    }
+
+    /// Sets the lifetime of the impersonated tokens.
+    pub fn lifetime(mut self, lifetime: Duration) -> Self {
+        self.lifetime = lifetime;
+        self
+    }
+
+    /// The email of the target service account.
+    pub(crate) fn target_principal(&self) -> &str {
+        &self.target_principal
+    }
+
+    /// The project of the target service account, taken from its email.
+    pub(crate) fn project_id(&self) -> Option<&str> {
+        project_id_from_email(&self.target_principal)
+    }
+
+    /// Exchanges `source_token` (a bearer token of the base credential) for a token of the
+    /// target service account with `scopes`.
+    pub(crate) async fn access_token(
+        &self,
+        http_client: &Client,
+        source_token: &str,
+        scopes: &str,
+    ) -> Result<Token> {
+        let url = format!(
+            "https://iamcredentials.googleapis.com/v1/projects/-/serviceAccounts/{}:generateAccessToken",
+            self.target_principal
+        );
+
+        generate_access_token(
+            http_client,
+            &url,
+            source_token,
+            &self.delegates,
+            scopes,
+            self.lifetime,
+        )
+        .await
+    }
+}
+
+/// The `impersonated_service_account` credential JSON written by
+/// `gcloud auth application-default login --impersonate-service-account`.
+#[derive(Clone, Deserialize)]
+pub(crate) struct ImpersonatedServiceAccount {
+    pub service_account_impersonation_url: String,
+    #[serde(default)]
+    pub delegates: Vec<String>,
+    /// The base credential, itself a credential JSON object, parsed so that its secrets are
+    /// zeroized as well.
+    pub source_credentials: Box<GAuthCredential>,
+}
+
+impl fmt::Debug for ImpersonatedServiceAccount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ImpersonatedServiceAccount")
+            .field(
+                "service_account_impersonation_url",
+                &self.service_account_impersonation_url,
+            )
+            .field("delegates", &self.delegates)
+            .field("source_credentials", &MASK)
+            .finish()
+    }
+}
+
+impl ImpersonatedServiceAccount {
+    /// The project of the target service account, taken from the impersonation URL.
+    pub fn project_id(&self) -> Option<&str> {
+        impersonation_url_project_id(&self.service_account_impersonation_url)
+    }
+
+    /// The email of the target service account, or the impersonation URL.
+    pub fn identity(&self) -> &str {
+        impersonation_url_email(&self.service_account_impersonation_url)
+            .unwrap_or(&self.service_account_impersonation_url)
+    }
+
+    pub async fn access_token(
+        &self,
+        http_client: &Client,
+        source_token: &str,
+        scopes: &str,
+    ) -> Result<Token> {
+        generate_access_token(
+            http_client,
+            &self.service_account_impersonation_url,
+            source_token,
+            &self.delegates,
+            scopes,
+            DEFAULT_LIFETIME,
+        )
+        .await
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerateAccessTokenResponse {
+    access_token: String,
+    expire_time: String,
+}
+
+pub(super) async fn generate_access_token(
+    http_client: &Client,
+    url: &str,
+    source_token: &str,
+    delegates: &[String],
+    scopes: &str,
+    lifetime: Duration,
+) -> Result<Token> {
+    let delegates: Vec<String> = delegates
+        .iter()
+        .map(|delegate| format!("projects/-/serviceAccounts/{delegate}"))
+        .collect();
+
+    let response = http_client
+        .post(url)
+        .header("Authorization", source_token)
+        .json(&json!({
+            "delegates": delegates,
+            "scope": scopes.split_whitespace().collect::<Vec<_>>(),
+            "lifetime": format!("{}s", lifetime.as_secs()),
+        }))
+        .send()
+        .await?;
+    let response = token_response::<GenerateAccessTokenResponse>(response).await?;
+
+    let expire_time = chrono::DateTime::parse_from_rfc3339(&response.expire_time)
+        .map_err(|err| GAuthError::InvalidResponse(format!("expireTime: {err}")))?;
+    let expires_in = (expire_time.timestamp() - chrono::Utc::now().timestamp()).max(0);
+
+    Ok(Token {
+        access_token: response.access_token,
+        expires_in: expires_in as u64,
+        token_type: String::from("Bearer"),
+    })
+}
+
+/// The email of the service account a `generateAccessToken` URL impersonates.
+pub(super) fn impersonation_url_email(url: &str) -> Option<&str> {
+    url.rsplit_once("/serviceAccounts/")
+        .and_then(|(_, target)| target.split_once(':'))
+        .map(|(email, _)| email)
+}
+
+/// The project of the service account a `generateAccessToken` URL impersonates.
+pub(super) fn impersonation_url_project_id(url: &str) -> Option<&str> {
+    impersonation_url_email(url).and_then(project_id_from_email)
+}
+
+/// `name@PROJECT.iam.gserviceaccount.com` -> `PROJECT`
+pub(crate) fn project_id_from_email(email: &str) -> Option<&str> {
+    email
+        .split_once('@')
+        .and_then(|(_, domain)| domain.strip_suffix(".iam.gserviceaccount.com"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_impersonated_service_account() {
+        let credential: ImpersonatedServiceAccount = serde_json::from_str(
+            r#"{
+                "type": "impersonated_service_account",
+                "service_account_impersonation_url": "https://iamcredentials.googleapis.com/v1/projects/-/serviceAccounts/logger@my-project.iam.gserviceaccount.com:generateAccessToken",
+                "delegates": [],
+                "source_credentials": {
+                    "type": "authorized_user",
+                    "client_id": "id",
+                    "client_secret": "secret",
+                    "refresh_token": "refresh"
+                }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(credential.project_id(), Some("my-project"));
+        assert_eq!(
+            Impersonation::new("logger@other.iam.gserviceaccount.com").project_id(),
+            Some("other")
+        );
+        assert_eq!(project_id_from_email("jane@example.com"), None);
+    }
+}
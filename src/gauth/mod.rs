@@ -1,22 +1,49 @@
-use std::path::Path;
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
 
 use errors::Result;
-use reqwest::Client;
+use reqwest::{Client, StatusCode};
+use serde::de::DeserializeOwned;
+use serde_derive::Deserialize;
 
-use self::jwt::{JwtToken, Token};
-use crate::utils::timestamp;
+use self::jwt::{JwtToken, ServiceAccountKey, Token};
+use self::signer::RingSigner;
+use crate::{redaction::MASK, secret::SecretBytes, utils::timestamp};
 
+pub use self::credential::GAuthCredential;
 pub use self::errors::GAuthError;
-pub use jwt::GAuthCredential;
+pub use self::impersonation::Impersonation;
+pub use self::signer::{JwtSigner, SignFuture};
 
+mod authorized_user;
+mod credential;
 mod errors;
+mod external_account;
+mod impersonation;
 mod jwt;
+mod pem;
+mod signer;
 
-#[derive(Debug, Default, Clone)]
+/// Scope the base credential needs to call the IAM Credentials API.
+const CLOUD_PLATFORM_SCOPE: &str = "https://www.googleapis.com/auth/cloud-platform";
+/// Refresh tokens this many seconds before they expire.
+const EXPIRY_MARGIN: u64 = 30;
+
+#[derive(Default, Clone)]
 pub struct GAuth {
     scopes: String,
-    gauth_key_bytes: Vec<u8>,
+    gauth_key_bytes: SecretBytes,
     user_email: Option<String>,
+    impersonation: Option<Impersonation>,
+    self_signed_jwt: bool,
+    /// Signs service account JWTs instead of the key's `private_key`.
+    signer: Option<Arc<dyn JwtSigner>>,
+    /// The file `gauth_key_bytes` was read from, watched for rotations.
+    key_file: Option<KeyFile>,
 
     access_token: Option<String>,
     expires_at: Option<u64>,
@@ -24,72 +51,615 @@ pub struct GAuth {
     http_client: Client,
 }
 
+/// A credential file and the modification time of the version in use.
+#[derive(Debug, Clone)]
+struct KeyFile {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
 impl GAuth {
-    #[allow(dead_code)]
     /// Creates a new service account from a key file and scopes
+    ///
+    /// The file is watched: when it changes (checked whenever a new token is needed), or when the
+    /// token endpoint returns `invalid_grant`, it is read again and the new credential
+    /// replaces the old one.
     pub fn from_file(key_path: impl AsRef<Path>, scopes: &[&str]) -> Result<Self> {
-        let bytes = std::fs::read(key_path.as_ref()).map_err(|err| {
-            GAuthError::ReadKey(format!("{}: {}", err, key_path.as_ref().display()))
-        })?;
+        let path = key_path.as_ref().to_path_buf();
+        let modified = modified(&path);
+        let bytes = std::fs::read(&path)
+            .map_err(|err| GAuthError::ReadKey(format!("{}: {}", err, path.display())))?;
 
-        Ok(Self::from_bytes(&bytes, scopes))
+        Ok(Self {
+            scopes: scopes.join(" "),
+            gauth_key_bytes: SecretBytes::from(bytes),
+            key_file: Some(KeyFile { path, modified }),
+            ..Default::default()
+        })
     }
 
     pub fn from_bytes(bytes: &[u8], scopes: &[&str]) -> Self {
         Self {
             scopes: scopes.join(" "),
-            gauth_key_bytes: bytes.to_vec(),
+            gauth_key_bytes: SecretBytes::from(bytes),
             ..Default::default()
         }
     }
 
-    fn access_token_inner(&mut self, token: Token) -> Result<String> {
-        match (self.access_token.as_ref(), self.expires_at) {
-            (Some(access_token), Some(expires_at)) if expires_at > timestamp()? => {
-                Ok(access_token.to_string())
-            }
-            _ => {
-                let expires_at = timestamp()? + token.expires_in - 30;
+    /// Impersonates a service account on top of the configured credential.
+    pub fn with_impersonation(mut self, impersonation: Impersonation) -> Self {
+        self.impersonation = Some(impersonation);
+        self.access_token = None;
+        self.expires_at = None;
+        self
+    }
 
-                self.access_token = Some(token.bearer_token());
-                self.expires_at = Some(expires_at);
+    /// Requests tokens with `scopes` instead of the ones given at construction.
+    pub fn with_scopes(mut self, scopes: &[impl AsRef<str>]) -> Self {
+        self.scopes = scopes
+            .iter()
+            .map(AsRef::as_ref)
+            .collect::<Vec<_>>()
+            .join(" ");
+        self.access_token = None;
+        self.expires_at = None;
+        self
+    }
+
+    /// Acts on behalf of the user `subject` through domain-wide delegation (the JWT `sub`).
+    ///
+    /// Only service account keys support a subject.
+    pub fn with_subject(mut self, subject: impl Into<String>) -> Self {
+        self.user_email = Some(subject.into());
+        self.access_token = None;
+        self.expires_at = None;
+        self
+    }
 
-                Ok(token.bearer_token())
+    /// Checks the configuration without calling Google: scopes are set, a subject is only
+    /// used with a service account key, a service account key without a signer has a
+    /// usable private key, and an external account does not use an AWS credential source.
+    pub fn validate(&self) -> Result<()> {
+        if self.scopes.trim().is_empty() {
+            return Err(GAuthError::InvalidConfig("no scopes".to_owned()));
+        }
+
+        match GAuthCredential::from_bytes(self.gauth_key_bytes.expose())? {
+            GAuthCredential::ServiceAccount(key) if self.signer.is_none() => {
+                RingSigner::from_private_key(&key.private_key)?;
+            }
+            GAuthCredential::ServiceAccount(_) => {}
+            _ if self.user_email.is_some() => {
+                return Err(GAuthError::InvalidConfig(
+                    "a subject needs a service account key".to_owned(),
+                ));
             }
+            GAuthCredential::ExternalAccount(credential) => credential.validate()?,
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Signs service account JWTs with `signer` instead of the key's `private_key`.
+    pub fn with_signer(mut self, signer: Arc<dyn JwtSigner>) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// Uses self-signed JWTs as access tokens for service account keys, skipping the
+    /// OAuth token exchange.
+    ///
+    /// Tokens are minted and cached locally, so only the called API needs to be
+    /// reachable. Keys with a domain-wide delegation subject still use the exchange.
+    pub fn with_self_signed_jwt(mut self, self_signed_jwt: bool) -> Self {
+        self.self_signed_jwt = self_signed_jwt;
+        self
+    }
+
+    /// Returns the project the credential belongs to, if it names one.
+    ///
+    /// With impersonation, this is the project of the target service account.
+    pub fn project_id(&self) -> Result<Option<String>> {
+        if let Some(project_id) = self
+            .impersonation
+            .as_ref()
+            .and_then(Impersonation::project_id)
+        {
+            return Ok(Some(project_id.to_owned()));
         }
+
+        credential_project_id(self.gauth_key_bytes.expose())
+    }
+
+    /// Returns who the credential authenticates as, e.g. a service account email.
+    pub fn identity(&self) -> Result<String> {
+        if let Some(impersonation) = &self.impersonation {
+            return Ok(impersonation.target_principal().to_owned());
+        }
+
+        Ok(GAuthCredential::from_bytes(self.gauth_key_bytes.expose())?.identity())
+    }
+
+    fn access_token_inner(&mut self, token: Token) -> Result<String> {
+        let expires_at = timestamp()? + token.expires_in.saturating_sub(EXPIRY_MARGIN);
+
+        self.access_token = Some(token.bearer_token());
+        self.expires_at = Some(expires_at);
+
+        Ok(token.bearer_token())
     }
 
     /// Returns an access token
     /// If the access token is not expired, it will return the cached access token
-    /// Otherwise, it will exchange the JWT token for an access token
+    /// Otherwise, it will fetch a new one from the credential source
     pub async fn access_token(&mut self) -> Result<String> {
-        let jwt_token = self.jwt_token()?;
-        let token = self.exchange_jwt_token_for_access_token(jwt_token).await?;
+        if let (Some(access_token), Some(expires_at)) = (&self.access_token, self.expires_at)
+            && expires_at > timestamp()?
+        {
+            return Ok(access_token.clone());
+        }
+
+        // the key file is only checked when a new token is needed
+        self.reload_key_file(false).await;
+
+        let token = match self.fetch_token().await {
+            // the key may have been rotated (and revoked) before its file changed
+            Err(err) if err.is_invalid_grant() && self.reload_key_file(true).await => {
+                tracing::warn!("Token fetch failed, retrying with the reloaded key: {err}");
+                self.fetch_token().await?
+            }
+            result => result?,
+        };
 
         self.access_token_inner(token)
     }
 
-    async fn exchange_jwt_token_for_access_token(&mut self, jwt_token: JwtToken) -> Result<Token> {
-        self.http_client
-            .post(jwt_token.token_uri())
-            .form(&[
-                ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
-                ("assertion", &jwt_token.to_string()?),
-            ])
-            .send()
-            .await?
-            .json::<Token>()
+    async fn fetch_token(&self) -> Result<Token> {
+        let Some(impersonation) = &self.impersonation else {
+            return self
+                .credential_token(
+                    self.gauth_key_bytes.expose(),
+                    self.user_email.as_deref(),
+                    self.signer.as_ref(),
+                    &self.scopes,
+                )
+                .await;
+        };
+
+        let source_token = self
+            .credential_token(
+                self.gauth_key_bytes.expose(),
+                self.user_email.as_deref(),
+                self.signer.as_ref(),
+                CLOUD_PLATFORM_SCOPE,
+            )
+            .await?;
+
+        impersonation
+            .access_token(
+                &self.http_client,
+                &source_token.bearer_token(),
+                &self.scopes,
+            )
             .await
-            .map_err(Into::into)
     }
 
-    fn jwt_token(&self) -> Result<JwtToken> {
-        let token = JwtToken::from_bytes(&self.gauth_key_bytes)?;
+    /// Fetches a token with `scopes` for the credential JSON `bytes`.
+    ///
+    /// Service account JWTs are signed by `signer`, or the key's `private_key` without one.
+    /// With `self_signed_jwt`, service account keys without a `user_email` mint the token
+    /// locally instead.
+    async fn credential_token(
+        &self,
+        bytes: &[u8],
+        user_email: Option<&str>,
+        signer: Option<&Arc<dyn JwtSigner>>,
+        scopes: &str,
+    ) -> Result<Token> {
+        self.parsed_credential_token(
+            GAuthCredential::from_bytes(bytes)?,
+            user_email,
+            signer,
+            scopes,
+        )
+        .await
+    }
+
+    /// [`credential_token`](Self::credential_token) for an already parsed `credential`.
+    async fn parsed_credential_token(
+        &self,
+        credential: GAuthCredential,
+        user_email: Option<&str>,
+        signer: Option<&Arc<dyn JwtSigner>>,
+        scopes: &str,
+    ) -> Result<Token> {
+        let http_client = &self.http_client;
 
-        Ok(match self.user_email {
-            Some(ref user_email) => token.sub(user_email.to_string()),
-            None => token,
+        match credential {
+            GAuthCredential::ServiceAccount(key)
+                if self.self_signed_jwt && user_email.is_none() =>
+            {
+                let jwt_token = jwt_token(key, signer)?
+                    .self_signed()
+                    .scope(scopes.to_owned());
+                Ok(Token {
+                    access_token: jwt_token.to_string().await?,
+                    expires_in: jwt_token.expires_in(),
+                    token_type: String::from("Bearer"),
+                })
+            }
+            GAuthCredential::ServiceAccount(key) => {
+                let jwt_token = match user_email {
+                    Some(user_email) => jwt_token(key, signer)?.sub(user_email.to_string()),
+                    None => jwt_token(key, signer)?,
+                }
+                .scope(scopes.to_owned());
+                exchange_jwt_token_for_access_token(http_client, jwt_token).await
+            }
+            GAuthCredential::ImpersonatedServiceAccount(credential) => {
+                // the configured signer belongs to the top-level key, not to the source key
+                let source_token = Box::pin(self.parsed_credential_token(
+                    (*credential.source_credentials).clone(),
+                    None,
+                    None,
+                    CLOUD_PLATFORM_SCOPE,
+                ))
+                .await?;
+
+                credential
+                    .access_token(http_client, &source_token.bearer_token(), scopes)
+                    .await
+            }
+            GAuthCredential::ExternalAccount(credential) => {
+                credential.access_token(http_client, scopes).await
+            }
+            GAuthCredential::AuthorizedUser(credential) => {
+                credential.access_token(http_client).await
+            }
+        }
+    }
+
+    /// Reads the key file again if it was modified (or unconditionally with `force`).
+    ///
+    /// Returns whether the credential changed. Unreadable or partially written files are
+    /// skipped, keeping the current credential until the next check.
+    async fn reload_key_file(&mut self, force: bool) -> bool {
+        let Some(key_file) = &mut self.key_file else {
+            return false;
+        };
+        let modified = tokio::fs::metadata(&key_file.path)
+            .await
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        if !force && modified == key_file.modified {
+            return false;
+        }
+
+        let bytes = match tokio::fs::read(&key_file.path).await {
+            Ok(bytes) => SecretBytes::from(bytes),
+            Err(err) => {
+                tracing::warn!("Failed to reload {}: {err}", key_file.path.display());
+                return false;
+            }
+        };
+        if bytes.expose() == self.gauth_key_bytes.expose() {
+            key_file.modified = modified;
+            return false;
+        }
+        if let Err(err) = GAuthCredential::from_bytes(bytes.expose()) {
+            tracing::warn!("Failed to reload {}: {err}", key_file.path.display());
+            return false;
         }
-        .scope(self.scopes.clone()))
+
+        tracing::info!("Reloaded credential from {}", key_file.path.display());
+        key_file.modified = modified;
+        self.gauth_key_bytes = bytes;
+        self.access_token = None;
+        self.expires_at = None;
+
+        true
+    }
+}
+
+impl fmt::Debug for GAuth {
+    /// Shows who the credential authenticates as, never the key or the cached token.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let credential = GAuthCredential::from_bytes(self.gauth_key_bytes.expose()).ok();
+
+        f.debug_struct("GAuth")
+            .field("identity", &self.identity().ok())
+            .field("project_id", &self.project_id().ok().flatten())
+            .field(
+                "key_id",
+                &credential.as_ref().and_then(GAuthCredential::key_id),
+            )
+            .field(
+                "key_file",
+                &self.key_file.as_ref().map(|key_file| &key_file.path),
+            )
+            .field("scopes", &self.scopes)
+            .field("subject", &self.user_email)
+            .field("impersonation", &self.impersonation)
+            .field("self_signed_jwt", &self.self_signed_jwt)
+            .field("signer", &self.signer)
+            .field("access_token", &self.access_token.as_ref().map(|_| MASK))
+            .field("expires_at", &self.expires_at)
+            .finish_non_exhaustive()
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// A JWT for `key`, signed by `signer` or the key's `private_key`.
+fn jwt_token(key: ServiceAccountKey, signer: Option<&Arc<dyn JwtSigner>>) -> Result<JwtToken> {
+    match signer {
+        Some(signer) => JwtToken::with_signer(key, signer.clone()),
+        None => JwtToken::new(key),
+    }
+}
+
+/// Returns the project named by the credential JSON `bytes`, if any.
+pub fn credential_project_id(bytes: &[u8]) -> Result<Option<String>> {
+    Ok(GAuthCredential::from_bytes(bytes)?
+        .project_id()
+        .map(str::to_owned))
+}
+
+async fn exchange_jwt_token_for_access_token(
+    http_client: &Client,
+    jwt_token: JwtToken,
+) -> Result<Token> {
+    let response = http_client
+        .post(jwt_token.token_uri())
+        .form(&[
+            ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+            ("assertion", &jwt_token.to_string().await?),
+        ])
+        .send()
+        .await?;
+
+    token_response(response).await
+}
+
+/// Error body of OAuth 2.0 (RFC 6749, section 5.2) and Google API token endpoints.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TokenErrorBody {
+    OAuth {
+        error: String,
+        error_description: Option<String>,
+    },
+    Google {
+        error: GoogleErrorStatus,
+    },
+}
+
+#[derive(Deserialize)]
+struct GoogleErrorStatus {
+    status: Option<String>,
+    message: Option<String>,
+}
+
+/// Parses a token endpoint response, turning error statuses into
+/// [`GAuthError::TokenEndpoint`].
+async fn token_response<T: DeserializeOwned>(response: reqwest::Response) -> Result<T> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response.json::<T>().await?);
+    }
+
+    let body = response.text().await.unwrap_or_default();
+    Err(token_error(status, body))
+}
+
+fn token_error(status: StatusCode, body: String) -> GAuthError {
+    let (error, description) = match serde_json::from_str::<TokenErrorBody>(&body) {
+        Ok(TokenErrorBody::OAuth {
+            error,
+            error_description,
+        }) => (error, error_description),
+        Ok(TokenErrorBody::Google { error }) => (
+            error.status.unwrap_or_else(|| status.to_string()),
+            error.message,
+        ),
+        Err(_) => (
+            status.canonical_reason().unwrap_or("error").to_owned(),
+            Some(body).filter(|body| !body.is_empty()),
+        ),
+    };
+
+    GAuthError::TokenEndpoint {
+        status: status.as_u16(),
+        error,
+        description,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVICE_ACCOUNT_KEY: &[u8] =
+        include_bytes!("../../test_fixtures/service-account-key.json");
+
+    #[test]
+    fn test_validate() {
+        let gauth = GAuth::from_bytes(SERVICE_ACCOUNT_KEY, &[CLOUD_PLATFORM_SCOPE]);
+        assert!(gauth.validate().is_ok());
+        assert!(
+            gauth
+                .clone()
+                .with_subject("jane@example.com")
+                .validate()
+                .is_ok()
+        );
+        assert!(matches!(
+            gauth.with_scopes(&[] as &[&str]).validate(),
+            Err(GAuthError::InvalidConfig(_))
+        ));
+
+        let authorized_user = br#"{
+            "type": "authorized_user",
+            "client_id": "id",
+            "client_secret": "secret",
+            "refresh_token": "refresh"
+        }"#;
+        let gauth = GAuth::from_bytes(authorized_user, &[CLOUD_PLATFORM_SCOPE]);
+        assert!(gauth.validate().is_ok());
+        assert!(matches!(
+            gauth.with_subject("jane@example.com").validate(),
+            Err(GAuthError::InvalidConfig(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_signer() {
+        struct FixedSigner;
+
+        impl JwtSigner for FixedSigner {
+            fn sign<'a>(&'a self, _message: &'a [u8]) -> SignFuture<'a> {
+                Box::pin(async { Ok(b"signature".to_vec()) })
+            }
+        }
+
+        let key = br#"{
+            "type": "service_account",
+            "project_id": "my-project",
+            "client_email": "logger@my-project.iam.gserviceaccount.com"
+        }"#;
+        let mut gauth = GAuth::from_bytes(key, &[CLOUD_PLATFORM_SCOPE]);
+        assert!(matches!(gauth.validate(), Err(GAuthError::PemMissingBlock)));
+
+        gauth = gauth
+            .with_signer(Arc::new(FixedSigner))
+            .with_self_signed_jwt(true);
+        assert!(gauth.validate().is_ok());
+        let token = gauth.access_token().await.unwrap();
+        assert!(token.starts_with("Bearer "));
+        assert!(token.ends_with(".c2lnbmF0dXJl"));
+    }
+
+    #[tokio::test]
+    async fn test_signer_is_not_used_for_source_credentials() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        #[derive(Default)]
+        struct CountingSigner(AtomicUsize);
+
+        impl JwtSigner for CountingSigner {
+            fn sign<'a>(&'a self, _message: &'a [u8]) -> SignFuture<'a> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                Box::pin(async { Ok(b"signature".to_vec()) })
+            }
+        }
+
+        let source: serde_json::Value = serde_json::from_slice(include_bytes!(
+            "../../test_fixtures/service-account-key.json"
+        ))
+        .unwrap();
+        let credential = serde_json::json!({
+            "type": "impersonated_service_account",
+            // unreachable, the source token is minted before
+            "service_account_impersonation_url": "http://127.0.0.1:1/v1/projects/-/serviceAccounts/logger@my-project.iam.gserviceaccount.com:generateAccessToken",
+            "source_credentials": source,
+        });
+        let signer = Arc::new(CountingSigner::default());
+        let mut gauth =
+            GAuth::from_bytes(credential.to_string().as_bytes(), &[CLOUD_PLATFORM_SCOPE])
+                .with_signer(signer.clone())
+                .with_self_signed_jwt(true);
+
+        // the source key signs its own JWT with its `private_key`
+        assert!(gauth.access_token().await.is_err());
+        assert_eq!(signer.0.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_debug_masks_secrets() {
+        let mut gauth = GAuth::from_bytes(SERVICE_ACCOUNT_KEY, &[CLOUD_PLATFORM_SCOPE]);
+        gauth.access_token = Some(String::from("Bearer ya29.secret"));
+        let debug = format!("{gauth:?}");
+
+        assert!(debug.contains("iam.gserviceaccount.com"));
+        assert!(debug.contains(r#"key_id: Some(""#));
+        assert!(!debug.contains("PRIVATE KEY"));
+        assert!(!debug.contains("ya29"));
+
+        let authorized_user = br#"{
+            "type": "authorized_user",
+            "client_id": "id",
+            "client_secret": "client-secret",
+            "refresh_token": "refresh-token"
+        }"#;
+        let credential = GAuthCredential::from_bytes(authorized_user).unwrap();
+        let debug = format!("{credential:?}");
+        assert!(!debug.contains("client-secret"));
+        assert!(!debug.contains("refresh-token"));
+    }
+
+    #[test]
+    fn test_token_error() {
+        let error = token_error(
+            StatusCode::BAD_REQUEST,
+            r#"{"error":"invalid_grant","error_description":"Invalid JWT Signature."}"#.to_owned(),
+        );
+        assert!(error.is_invalid_grant());
+        assert!(!error.is_retryable());
+        assert_eq!(
+            error.to_string(),
+            "token endpoint returned 400 `invalid_grant`: Invalid JWT Signature."
+        );
+
+        let error = token_error(
+            StatusCode::FORBIDDEN,
+            r#"{"error":{"code":403,"message":"Permission denied","status":"PERMISSION_DENIED"}}"#
+                .to_owned(),
+        );
+        assert!(matches!(
+            &error,
+            GAuthError::TokenEndpoint { error, .. } if error == "PERMISSION_DENIED"
+        ));
+        assert!(!error.is_retryable());
+
+        let error = token_error(StatusCode::SERVICE_UNAVAILABLE, "upstream error".to_owned());
+        assert!(matches!(
+            &error,
+            GAuthError::TokenEndpoint { error, description: Some(_), .. } if error == "Service Unavailable"
+        ));
+        assert!(error.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn test_reload_key_file() {
+        let path = std::env::temp_dir().join(format!("gauth-reload-{}.json", std::process::id()));
+        std::fs::write(&path, SERVICE_ACCOUNT_KEY).unwrap();
+
+        let mut gauth = GAuth::from_file(&path, &[CLOUD_PLATFORM_SCOPE]).unwrap();
+        gauth.access_token = Some(String::from("Bearer cached"));
+        assert!(!gauth.reload_key_file(false).await);
+        assert!(!gauth.reload_key_file(true).await);
+
+        // a partially written file keeps the current key
+        std::fs::write(&path, b"{").unwrap();
+        assert!(!gauth.reload_key_file(true).await);
+        assert_eq!(gauth.gauth_key_bytes.expose(), SERVICE_ACCOUNT_KEY);
+
+        let rotated = br#"{
+            "type": "authorized_user",
+            "client_id": "id",
+            "client_secret": "secret",
+            "refresh_token": "refresh"
+        }"#;
+        std::fs::write(&path, rotated).unwrap();
+        assert!(gauth.reload_key_file(true).await);
+        assert_eq!(gauth.gauth_key_bytes.expose(), rotated);
+        assert_eq!(gauth.access_token, None);
+
+        std::fs::remove_file(path).unwrap();
     }
 }
@@ -0,0 +1,85 @@
+use std::{error::Error, fmt, future::Future, pin::Pin};
+
+use ring::{rand, signature};
+
+use super::errors::{GAuthError, Result};
+use super::pem::{PrivateKeyDer, parse_private_key};
+
+/// The future returned by [`JwtSigner::sign`].
+pub type SignFuture<'a> = Pin<
+    Box<
+        dyn Future<Output = std::result::Result<Vec<u8>, Box<dyn Error + Send + Sync>>> + Send + 'a,
+    >,
+>;
+
+/// Signs service account JWTs with RS256 (RSASSA-PKCS1-v1_5 using SHA-256).
+///
+/// By default, JWTs are signed in memory with the key's `private_key`. Implement this
+/// trait to keep the key non-exportable, e.g. behind Cloud KMS `asymmetricSign`, a
+/// PKCS#11 token or IAM Credentials `signJwt`; the credential JSON then needs no
+/// `private_key`.
+///
+/// ```
+/// use tracing_gcloud_layer::{JwtSigner, SignFuture};
+///
+/// struct KmsSigner {
+///     key_version: String,
+/// }
+///
+/// impl JwtSigner for KmsSigner {
+///     fn sign<'a>(&'a self, message: &'a [u8]) -> SignFuture<'a> {
+///         Box::pin(async move {
+///             // call `asymmetricSign` with the SHA-256 digest of `message`
+///             let _ = (&self.key_version, message);
+///             Err("not implemented".into())
+///         })
+///     }
+/// }
+/// ```
+pub trait JwtSigner: Send + Sync + 'static {
+    /// Returns the RS256 signature of `message`, the JWS signing input.
+    fn sign<'a>(&'a self, message: &'a [u8]) -> SignFuture<'a>;
+}
+
+impl fmt::Debug for dyn JwtSigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("JwtSigner")
+    }
+}
+
+/// Signs in memory with `ring`, from the `private_key` of a service account key
+/// (PKCS#8 or PKCS#1 PEM).
+pub(crate) struct RingSigner {
+    key_pair: signature::RsaKeyPair,
+}
+
+impl RingSigner {
+    pub fn from_private_key(private_key: &str) -> Result<Self> {
+        let key_pair = match parse_private_key(private_key)? {
+            PrivateKeyDer::Pkcs8(der) => signature::RsaKeyPair::from_pkcs8(&der)
+                .map_err(|err| GAuthError::RsaKeyPair(format!("invalid PKCS#8 key: {err}")))?,
+            PrivateKeyDer::Pkcs1(der) => signature::RsaKeyPair::from_der(&der)
+                .map_err(|err| GAuthError::RsaKeyPair(format!("invalid PKCS#1 key: {err}")))?,
+        };
+
+        Ok(Self { key_pair })
+    }
+
+    /// Signs a message with the private key
+    pub fn sign_rsa(&self, message: &[u8]) -> Result<Vec<u8>> {
+        // Sign the message, using PKCS#1 v1.5 padding and the SHA256 digest algorithm.
+        let rng = rand::SystemRandom::new();
+        let mut signature = vec![0; self.key_pair.public().modulus_len()];
+        self.key_pair
+            .sign(&signature::RSA_PKCS1_SHA256, &rng, message, &mut signature)
+            .map_err(|err| GAuthError::RsaSign(format!("{}", err)))?;
+
+        Ok(signature)
+    }
+}
+
+impl JwtSigner for RingSigner {
+    fn sign<'a>(&'a self, message: &'a [u8]) -> SignFuture<'a> {
+        Box::pin(async move { self.sign_rsa(message).map_err(Into::into) })
+    }
+}
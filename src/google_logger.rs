@@ -1,23 +1,37 @@
-use std::sync::Arc;
+use std::{collections::BTreeMap, fmt, path::Path, sync::Arc};
 
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
 use thiserror::Error;
 
-use super::gauth::{GAuth, GAuthCredential, GAuthError};
+use super::gauth::{GAuth, GAuthError, Impersonation, JwtSigner};
+use crate::{
+    log_entry::Resource,
+    redaction::Redactor,
+    routing::{Route, route},
+};
 
-/// Google Cloud Logging API endpoint for writing log entries.
-const WRITE_URL: &str = "https://logging.googleapis.com/v2/entries:write";
+/// Google Cloud Logging API endpoint.
+const LOGGING_ENDPOINT: &str = "https://logging.googleapis.com";
 /// OAuth 2.0 scope for logging write access.
-const SCOPES: [&str; 1] = ["https://www.googleapis.com/auth/logging.write"];
+pub(crate) const SCOPES: [&str; 1] = ["https://www.googleapis.com/auth/logging.write"];
 
-#[derive(Debug, Clone)]
+/// Project and log name an entry is written to.
+type Destination = (Arc<str>, Arc<str>);
+
+#[derive(Debug, Clone, Default)]
 pub struct LogContext {
     /// The log label associated with the logger (e.g., log name).
     pub log_label: Arc<str>,
     /// The GCP project ID where logs should be written.
     pub project_id: Arc<str>,
+    /// Static labels attached to every entry (e.g. `env`, `version`, `region`).
+    pub labels: Arc<BTreeMap<String, String>>,
+    /// Names of event or span fields promoted to labels (e.g. `tenant_id`).
+    pub fields_as_labels: Arc<[String]>,
+    /// Monitored resource chosen by a [`Route`]; `None` means the project's `global` resource.
+    pub resource: Option<Resource>,
 }
 
 /// Trait for mapping a raw JSON log entry to a structured format compatible with Google Cloud Logging.
@@ -31,12 +45,29 @@ pub trait LogMapper: Send + Sync + Clone + Default + 'static {
 }
 
 /// A logger that writes entries to Google Cloud Logging using the [entries.write](https://cloud.google.com/logging/docs/reference/v2/rest/v2/entries/write) API.
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct GoogleLogger<M: LogMapper> {
     log_context: LogContext,
     gauth: GAuth,
     http_client: Client,
+    /// `entries.write` URL of the configured endpoint.
+    write_url: Arc<str>,
     mapper: M,
+    routes: Arc<[Route]>,
+    redactor: Option<Arc<Redactor>>,
+}
+
+impl<M: LogMapper> fmt::Debug for GoogleLogger<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GoogleLogger")
+            .field("log_name", &self.log_context.log_label)
+            .field("project_id", &self.log_context.project_id)
+            .field("gauth", &self.gauth)
+            .field("write_url", &self.write_url)
+            .field("routes", &self.routes.len())
+            .field("redactor", &self.redactor.is_some())
+            .finish_non_exhaustive()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -59,66 +90,296 @@ pub enum LoggerError {
     Response(ResponseErrorInner),
     #[error("Service Account: {}", .0)]
     GAuth(#[from] GAuthError),
+    #[error("the credential names no project, set `project_id`")]
+    MissingProjectId,
+    #[error("no credential, set `logger_credential` or `logger_credential_file`")]
+    MissingCredential,
+}
+
+/// Google API statuses worth retrying.
+const RETRYABLE_STATUSES: [&str; 5] = [
+    "RESOURCE_EXHAUSTED",
+    "UNAVAILABLE",
+    "INTERNAL",
+    "DEADLINE_EXCEEDED",
+    "ABORTED",
+];
+
+fn write_url(endpoint: &str) -> Arc<str> {
+    Arc::from(format!(
+        "{}/v2/entries:write",
+        endpoint.trim_end_matches('/')
+    ))
+}
+
+impl LoggerError {
+    /// Whether writing the same entries again may succeed: network errors, rate limiting,
+    /// server errors and transient token errors.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Reqwest(_) => true,
+            Self::Response(error) => {
+                error
+                    .code
+                    .is_some_and(|code| code == 429 || (500..600).contains(&code))
+                    || RETRYABLE_STATUSES.contains(&error.status.as_str())
+            }
+            Self::GAuth(error) => error.is_retryable(),
+            Self::MissingProjectId | Self::MissingCredential => false,
+        }
+    }
+
+    /// Whether no write can succeed until the configuration changes, e.g. a revoked key,
+    /// a skewed clock or a missing project.
+    ///
+    /// Other non-retryable errors, like an entry rejected by the API, only fail their batch.
+    /// After a fatal error, the writer drops entries for
+    /// [`GoogleWriterConfig::fatal_error_cooldown`](crate::GoogleWriterConfig) before trying
+    /// again, so a fixed key file is picked up.
+    pub fn is_fatal(&self) -> bool {
+        match self {
+            Self::GAuth(error) => !error.is_retryable(),
+            Self::MissingProjectId | Self::MissingCredential => true,
+            Self::Reqwest(_) | Self::Response(_) => false,
+        }
+    }
+}
+
+/// Fails with the `error` of an API response, or with its status if it has no JSON body.
+///
+/// See <https://cloud.google.com/logging/docs/reference/v2/rest/v2/entries/write#response-body>.
+pub(crate) async fn check_response(response: reqwest::Response) -> Result<(), LoggerError> {
+    let status = response.status();
+    if let Ok(ResponseError { error }) = response.json::<ResponseError>().await {
+        return Err(LoggerError::Response(error));
+    }
+    if !status.is_success() {
+        return Err(LoggerError::Response(ResponseErrorInner {
+            code: Some(status.as_u16().into()),
+            message: status.canonical_reason().unwrap_or_default().to_owned(),
+            status: status.to_string(),
+        }));
+    }
+
+    Ok(())
 }
 
 impl<M: LogMapper> GoogleLogger<M> {
-    /// Creates a new `GoogleLogger` with the given log label, service account credentials, and log mapper.
+    /// Creates a new `GoogleLogger` with the given log label, credentials, and log mapper.
+    ///
+    /// Logs are written to the project named by the credential; credentials that name none
+    /// (e.g. `impersonated_service_account`) need [`with_project_id`](Self::with_project_id).
     pub fn new(
         log_label: Arc<str>,
         credential_bytes: impl AsRef<[u8]>,
         mapper: M,
     ) -> Result<GoogleLogger<M>, LoggerError> {
-        let credential_bytes = credential_bytes.as_ref();
-        let service_account = GAuth::from_bytes(credential_bytes, &SCOPES);
-        let project_id = GAuthCredential::from_bytes(credential_bytes)
-            .map_err(|e| LoggerError::GAuth(GAuthError::SerdeJson(e)))?
-            .project_id;
+        Self::with_gauth(
+            log_label,
+            GAuth::from_bytes(credential_bytes.as_ref(), &SCOPES),
+            mapper,
+        )
+    }
 
-        let project_id = Arc::from(project_id);
+    /// Creates a new `GoogleLogger` reading its credential from `credential_path`.
+    ///
+    /// The file is read again when it changes or its key is rejected, so rotated keys are
+    /// picked up without a restart and without dropping queued entries.
+    pub fn from_file(
+        log_label: Arc<str>,
+        credential_path: impl AsRef<Path>,
+        mapper: M,
+    ) -> Result<GoogleLogger<M>, LoggerError> {
+        Self::with_gauth(
+            log_label,
+            GAuth::from_file(credential_path, &SCOPES)?,
+            mapper,
+        )
+    }
+
+    pub(crate) fn with_gauth(
+        log_label: Arc<str>,
+        gauth: GAuth,
+        mapper: M,
+    ) -> Result<Self, LoggerError> {
+        let project_id = Arc::from(gauth.project_id()?.unwrap_or_default());
 
         Ok(Self {
             log_context: LogContext {
                 log_label,
                 project_id,
+                ..Default::default()
             },
-            gauth: service_account,
+            gauth,
             http_client: Client::new(),
+            write_url: write_url(LOGGING_ENDPOINT),
             mapper,
+            routes: Arc::from([]),
+            redactor: None,
         })
     }
 
     /// Sends a batch of log entries to Google Cloud Logging.
     ///
-    /// Each entry is passed through the configured `LogMapper` before being sent.
+    /// Each entry is redacted (if a [`Redactor`] is configured), routed to its destination
+    /// (see [`Route`]) and passed through the configured `LogMapper`. Entries are then grouped by destination project and log,
+    /// with one `entries.write` request per group.
     pub async fn write_logs(&mut self, log_entry: Vec<Value>) -> Result<(), LoggerError> {
+        if self.log_context.project_id.is_empty() {
+            return Err(LoggerError::MissingProjectId);
+        }
         let access_token = self.gauth.access_token().await?;
-        let entries = log_entry
-            .into_iter()
-            .map(|v| self.mapper.map(self.context(), v))
-            .collect::<Vec<_>>();
 
-        // https://cloud.google.com/logging/docs/reference/v2/rest/v2/entries/write#response-body
-        let maybe_response_error = self
+        let mut batches: Vec<(Destination, Vec<Value>)> = Vec::new();
+        for mut entry in log_entry {
+            if let Some(redactor) = &self.redactor {
+                redactor.redact(&mut entry);
+            }
+
+            let context = route(&self.routes, &self.log_context, &entry);
+            let destination = (context.project_id.clone(), context.log_label.clone());
+            let entry = self.mapper.map(context, entry);
+
+            match batches.iter_mut().find(|(key, _)| *key == destination) {
+                Some((_, entries)) => entries.push(entry),
+                None => batches.push((destination, vec![entry])),
+            }
+        }
+
+        let mut result = Ok(());
+        for (_, entries) in batches {
+            if let Err(err) = self.write_entries(&access_token, entries).await {
+                result = Err(err);
+            }
+        }
+
+        result
+    }
+
+    /// Sends already mapped entries in a single `entries.write` request.
+    ///
+    /// With `partialSuccess`, entries rejected by the API (reported as
+    /// `WriteLogEntriesPartialErrors`) do not keep the valid ones from being written.
+    async fn write_entries(
+        &self,
+        access_token: &str,
+        entries: Vec<Value>,
+    ) -> Result<(), LoggerError> {
+        let response = self
             .http_client
-            .post(WRITE_URL)
+            .post(&*self.write_url)
             .header("Content-Type", "application/json")
             .header("Authorization", access_token)
             .json(&json!({
                 "entries": entries,
+                "partialSuccess": true,
             }))
             .send()
-            .await?
-            .json::<ResponseError>()
-            .await
-            .ok();
+            .await?;
 
-        if let Some(ResponseError { error }) = maybe_response_error {
-            return Err(LoggerError::Response(error));
+        check_response(response).await
+    }
+
+    /// Sends entries to the Cloud Logging API at `endpoint` instead of
+    /// `https://logging.googleapis.com`, e.g. a Private Service Connect endpoint or a
+    /// local fake.
+    pub fn with_endpoint(mut self, endpoint: impl AsRef<str>) -> Self {
+        self.write_url = write_url(endpoint.as_ref());
+        self
+    }
+
+    /// Writes logs to `project_id` instead of the credential's project.
+    pub fn with_project_id(mut self, project_id: impl AsRef<str>) -> Self {
+        self.log_context.project_id = Arc::from(project_id.as_ref());
+        self
+    }
+
+    /// Authenticates as another service account, impersonated with the configured credential.
+    ///
+    /// Logs then go to the target service account's project; call
+    /// [`with_project_id`](Self::with_project_id) afterwards to pick another one.
+    pub fn with_impersonation(mut self, impersonation: Impersonation) -> Self {
+        self.gauth = self.gauth.with_impersonation(impersonation);
+        if let Ok(Some(project_id)) = self.gauth.project_id() {
+            self.log_context.project_id = Arc::from(project_id);
         }
+        self
+    }
 
+    /// Requests tokens with `scopes` instead of `logging.write`; empty `scopes` are ignored.
+    pub fn with_scopes(mut self, scopes: &[impl AsRef<str>]) -> Self {
+        if !scopes.is_empty() {
+            self.gauth = self.gauth.with_scopes(scopes);
+        }
+        self
+    }
+
+    /// Acts on behalf of the user `subject` through domain-wide delegation.
+    pub fn with_subject(mut self, subject: impl Into<String>) -> Self {
+        self.gauth = self.gauth.with_subject(subject);
+        self
+    }
+
+    /// Signs service account JWTs with `signer` (e.g. backed by Cloud KMS) instead of the
+    /// key's `private_key`.
+    pub fn with_signer(mut self, signer: Arc<dyn JwtSigner>) -> Self {
+        self.gauth = self.gauth.with_signer(signer);
+        self
+    }
+
+    /// Fetches an access token, failing with the credential or token endpoint error.
+    ///
+    /// The token is cached for the following writes.
+    pub async fn verify_credentials(&mut self) -> Result<(), LoggerError> {
+        self.gauth.access_token().await?;
         Ok(())
     }
 
+    /// Returns who the logger authenticates as, e.g. a service account email.
+    pub(crate) fn identity(&self) -> Result<String, LoggerError> {
+        Ok(self.gauth.identity()?)
+    }
+
+    /// Checks the project and credential configuration without calling Google.
+    pub(crate) fn validate(&self) -> Result<(), LoggerError> {
+        if self.log_context.project_id.is_empty() {
+            return Err(LoggerError::MissingProjectId);
+        }
+        self.gauth.validate()?;
+        Ok(())
+    }
+
+    /// Authenticates service account keys with self-signed JWTs instead of exchanging
+    /// them at the OAuth token endpoint, see [`GCloudLayerConfig`](crate::GCloudLayerConfig).
+    pub fn with_self_signed_jwt(mut self, self_signed_jwt: bool) -> Self {
+        self.gauth = self.gauth.with_self_signed_jwt(self_signed_jwt);
+        self
+    }
+
+    /// Sets the routing table used to pick each entry's destination.
+    pub fn with_routes(mut self, routes: Vec<Route>) -> Self {
+        self.routes = Arc::from(routes);
+        self
+    }
+
+    /// Sets the redaction stage applied to entries before they are mapped.
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = Some(Arc::new(redactor));
+        self
+    }
+
+    /// Sets the static labels attached to every entry.
+    pub fn with_labels(mut self, labels: BTreeMap<String, String>) -> Self {
+        self.log_context.labels = Arc::new(labels);
+        self
+    }
+
+    /// Sets the event or span fields promoted to labels.
+    pub fn with_fields_as_labels(mut self, fields: Vec<String>) -> Self {
+        self.log_context.fields_as_labels = Arc::from(fields);
+        self
+    }
+
     #[inline]
     pub fn context(&self) -> LogContext {
         self.log_context.clone()
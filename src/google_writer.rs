@@ -1,13 +1,218 @@
-use serde_json::Value;
-use std::{io::Write, pin::Pin, sync::Arc};
+use serde_json::{Map, Value, json};
+use std::{
+    io::Write,
+    pin::Pin,
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
 use tokio::{
+    runtime::{Handle, RuntimeFlavor},
     sync::{RwLock, mpsc, oneshot},
     task::JoinHandle,
-    time::Sleep,
+    time::{Interval, MissedTickBehavior, Sleep},
+};
+use tracing::Metadata;
+use tracing_subscriber::fmt::MakeWriter;
+
+use super::google_logger::{GoogleLogger, LogMapper, LoggerError};
+use crate::{
+    DefaultLogMapper, GoogleWriterConfig, LogSeverity,
+    error_capture::apply_captured,
+    sampling::{Callsite, Sampler},
+    utils::{
+        INSERT_ID_KEY, REPORT_LOCATION_KEY, SEVERITY_FIELD, SOURCE_LOCATION_KEY, next_insert_id,
+    },
 };
 
-use super::google_logger::{GoogleLogger, LogMapper};
-use crate::GoogleWriterConfig;
+/// A [`MakeWriter`] that hands out [`GoogleWriter`]s for the `tracing_stackdriver` layer.
+///
+/// Unlike a plain closure, it sees the [`Metadata`] of every event, which is used to
+/// attach the callsite as the entry's `sourceLocation` and to map the event level to
+/// a [`LogSeverity`].
+///
+/// All writers it hands out share one background task, started on the first event, so
+/// entries from every event (and every route) are batched together. Buffered entries are
+/// flushed every `max_delay`, and once more when the layer is dropped.
+///
+/// Clones share that task too.
+///
+/// With [`GoogleWriterConfig::sampling`] set, the writers also share the sampling and rate
+/// limiting state, so limits hold across threads.
+#[derive(Clone)]
+pub struct GoogleMakeWriter<M: LogMapper> {
+    logger: GoogleLogger<M>,
+    config: GoogleWriterConfig,
+    writer: Arc<OnceLock<GoogleWriter<M>>>,
+}
+
+impl<M: LogMapper> GoogleMakeWriter<M> {
+    pub fn new(logger: GoogleLogger<M>, config: GoogleWriterConfig) -> Self {
+        Self {
+            logger,
+            config,
+            writer: Arc::new(OnceLock::new()),
+        }
+    }
+
+    /// Returns a guard flushing the entries queued by the writers handed out.
+    pub fn flush_guard(&self) -> FlushGuard<M> {
+        FlushGuard {
+            writer: self.writer.clone(),
+            identity: self.logger.identity().ok(),
+        }
+    }
+}
+
+/// Flushes the entries queued by a layer when dropped, see
+/// [`GCloudLayerConfig::build_layer_with_guard`](crate::GCloudLayerConfig::build_layer_with_guard).
+///
+/// A subscriber installed with `.init()` is never dropped, so entries still queued when the
+/// process exits are lost unless they are flushed: keep the guard alive until the end of
+/// `main`, or call [`flush`](Self::flush).
+///
+/// Dropping the guard can only block on the flush on a multi-threaded runtime. On a
+/// current-thread runtime (`#[tokio::main(flavor = "current_thread")]`, `#[tokio::test]`)
+/// the background task cannot run while the runtime thread blocks, so the drop does not
+/// flush: call `flush().await` before the end of `main` instead.
+#[must_use = "dropping the guard flushes right away"]
+pub struct FlushGuard<M: LogMapper = DefaultLogMapper> {
+    writer: Arc<OnceLock<GoogleWriter<M>>>,
+    identity: Option<String>,
+}
+
+impl<M: LogMapper> FlushGuard<M> {
+    /// Who the layer authenticates as (a service account or user email, or a federated
+    /// principal), to be logged once the subscriber is installed.
+    pub fn identity(&self) -> Option<&str> {
+        self.identity.as_deref()
+    }
+
+    /// Writes the entries queued so far, returning once they are written or dropped.
+    pub async fn flush(&self) {
+        if let Some(writer) = self.writer.get() {
+            writer.batcher.flush().await;
+        }
+    }
+}
+
+impl<M: LogMapper> Drop for FlushGuard<M> {
+    fn drop(&mut self) {
+        if let Some(writer) = self.writer.get()
+            && let Some(runtime) = blocking_runtime()
+        {
+            tokio::task::block_in_place(|| runtime.block_on(writer.batcher.flush()));
+        }
+    }
+}
+
+/// Returns the current runtime if it can block on the background task from a `Drop`.
+///
+/// `block_in_place` panics on a current-thread runtime, whose only thread would also be
+/// the one needed to run the task.
+fn blocking_runtime() -> Option<Handle> {
+    Handle::try_current()
+        .ok()
+        .filter(|runtime| runtime.runtime_flavor() == RuntimeFlavor::MultiThread)
+}
+
+impl<'a, M: LogMapper> MakeWriter<'a> for GoogleMakeWriter<M> {
+    type Writer = GoogleWriter<M>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.writer
+            .get_or_init(|| GoogleWriter::new(self.logger.clone(), self.config.clone()))
+            .handle()
+    }
+
+    fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+        let mut writer = self.make_writer();
+        writer.event = Some(EventContext::new(meta, &self.config));
+        writer
+    }
+}
+
+/// What the writer knows about the event being written, taken from its [`Metadata`].
+struct EventContext {
+    source_location: Option<Value>,
+    /// Whether `source_location` is attached as the entry's `sourceLocation`, or only
+    /// passed on for Error Reporting.
+    attach_source_location: bool,
+    /// Set when sampling is enabled.
+    callsite: Option<Callsite>,
+    /// Severity the event level maps to.
+    level_severity: LogSeverity,
+    /// Whether the event declares a reserved `severity` field overriding its level.
+    severity_override: bool,
+}
+
+impl EventContext {
+    fn new(meta: &Metadata<'_>, config: &GoogleWriterConfig) -> Self {
+        Self {
+            source_location: source_location(meta, &config.source_path_prefixes),
+            attach_source_location: config.source_location,
+            callsite: config.sampling.is_some().then(|| Callsite::new(meta)),
+            level_severity: config.severity_mapping.severity(meta.level()),
+            severity_override: meta.fields().field(SEVERITY_FIELD).is_some(),
+        }
+    }
+
+    /// Adds the metadata-derived fields to a serialized entry, given the raw `severity`
+    /// field captured by the [`ErrorCaptureLayer`](crate::ErrorCaptureLayer).
+    fn apply(self, entry: &mut Map<String, Value>, severity_field: Option<&str>) {
+        if let Some(source_location) = self.source_location {
+            let key = if self.attach_source_location {
+                SOURCE_LOCATION_KEY
+            } else {
+                REPORT_LOCATION_KEY
+            };
+            entry.insert(key.to_owned(), source_location);
+        }
+
+        // a valid override is authoritative, even `DEFAULT`. `tracing_stackdriver` already
+        // consumed the field and turned invalid values into `DEFAULT`, so without the raw
+        // value only a severity other than `DEFAULT` counts as explicit
+        let severity = match severity_field {
+            Some(severity) => severity.parse::<LogSeverity>().ok(),
+            None => entry
+                .get(SEVERITY_FIELD)
+                .and_then(Value::as_str)
+                .and_then(|severity| severity.parse::<LogSeverity>().ok())
+                .filter(|severity| *severity != LogSeverity::Default),
+        }
+        .filter(|_| self.severity_override)
+        .unwrap_or(self.level_severity);
+        entry.insert(SEVERITY_FIELD.to_owned(), severity.as_str().into());
+    }
+}
+
+/// Builds a Cloud Logging [`LogEntrySourceLocation`] from the callsite metadata.
+///
+/// [`LogEntrySourceLocation`]: https://cloud.google.com/logging/docs/reference/v2/rest/v2/LogEntry#LogEntrySourceLocation
+fn source_location(meta: &Metadata<'_>, path_prefixes: &[String]) -> Option<Value> {
+    let file = meta.file()?;
+    let file = path_prefixes
+        .iter()
+        .find_map(|prefix| file.strip_prefix(prefix.as_str()))
+        .map(|file| file.trim_start_matches(['/', '\\']))
+        .unwrap_or(file);
+
+    let mut function = meta.module_path().unwrap_or(meta.target()).to_owned();
+    // events are named `event file:line` unless the callsite gives them a name
+    if !meta.name().starts_with("event ") {
+        function = format!("{function}::{}", meta.name());
+    }
+
+    let mut location = json!({
+        "file": file,
+        "function": function,
+    });
+    if let Some(line) = meta.line() {
+        // `line` is an int64, which is encoded as a string in JSON
+        location["line"] = json!(line.to_string());
+    }
+
+    Some(location)
+}
 
 /// An asynchronous log writer that batches entries before sending them to Google Cloud Logging.
 ///
@@ -18,9 +223,9 @@ use crate::GoogleWriterConfig;
 /// Batching behavior is controlled via [`GoogleWriterConfig`] — you can tune the flush interval,
 /// max batch size, and buffer limits.
 pub struct GoogleWriter<M: LogMapper> {
-    sender: mpsc::Sender<Value>,
-    shutdown_trigger: Option<oneshot::Sender<()>>,
-    shutdown_handle: Option<JoinHandle<()>>,
+    batcher: Batcher,
+    sampler: Option<Arc<Sampler>>,
+    event: Option<EventContext>,
     _marker: std::marker::PhantomData<M>,
 }
 
@@ -34,30 +239,173 @@ impl<M: LogMapper> GoogleWriter<M> {
     ///
     /// The logger will also flush immediately during shutdown.
     pub fn new(google_logger: GoogleLogger<M>, config: GoogleWriterConfig) -> Self {
-        let (tx, rx) = mpsc::channel::<Value>(config.buffer_size);
+        let sampler = config.sampling.clone().map(Sampler::new).map(Arc::new);
+
+        Self {
+            batcher: Batcher::spawn(google_logger, config, sampler.clone()),
+            sampler,
+            event: None,
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    /// Returns another writer feeding the same background task.
+    ///
+    /// The task is shut down (and flushed) once the last of these writers is dropped.
+    pub fn handle(&self) -> Self {
+        Self {
+            batcher: self.batcher.clone(),
+            sampler: self.sampler.clone(),
+            event: None,
+            _marker: std::marker::PhantomData,
+        }
+    }
+}
+
+/// Destination of the batches assembled by a [`Batcher`].
+pub(crate) trait BatchSink: Send + Sync + 'static {
+    fn write_batch(
+        &mut self,
+        batch: Vec<Value>,
+    ) -> impl Future<Output = Result<(), LoggerError>> + Send;
+}
+
+impl<M: LogMapper> BatchSink for GoogleLogger<M> {
+    async fn write_batch(&mut self, batch: Vec<Value>) -> Result<(), LoggerError> {
+        self.write_logs(batch).await
+    }
+}
+
+/// Delay before the first retry of a failed batch, doubled on each further retry.
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+/// Retries of a batch failing with a retryable error before it is dropped.
+const MAX_RETRIES: u32 = 4;
+
+/// Upper bound of the cooldown after repeated fatal errors.
+const MAX_FATAL_ERROR_COOLDOWN: Duration = Duration::from_secs(60 * 60);
+
+/// Pause of a [`Batcher`] after a fatal error, doubled after each further one and reset
+/// by the next batch that does not fail fatally.
+struct Cooldown {
+    initial: Duration,
+    next: Duration,
+    until: Option<tokio::time::Instant>,
+}
+
+impl Cooldown {
+    fn new(initial: Duration) -> Self {
+        Self {
+            initial,
+            next: initial,
+            until: None,
+        }
+    }
+
+    /// Whether entries are dropped right now.
+    fn is_active(&self) -> bool {
+        self.until
+            .is_some_and(|until| tokio::time::Instant::now() < until)
+    }
+
+    /// Starts a cooldown after a fatal error, or resets it otherwise.
+    fn record(&mut self, fatal: bool) {
+        if !fatal {
+            self.next = self.initial;
+            self.until = None;
+            return;
+        }
+
+        tracing::warn!(
+            "Dropping log entries for {:?}, then retrying with the current credential",
+            self.next
+        );
+        self.until = Some(tokio::time::Instant::now() + self.next);
+        self.next = (self.next * 2).min(MAX_FATAL_ERROR_COOLDOWN);
+    }
+}
+
+/// Message to the background task of a [`Batcher`].
+enum Message {
+    Entry(Value),
+    /// Flush the buffered entries, then notify the sender.
+    Flush(oneshot::Sender<()>),
+}
+
+/// Handle of a background task batching entries into a [`BatchSink`].
+///
+/// Clones feed the same task, which is shut down (and flushed) once the last one is dropped.
+#[derive(Clone)]
+pub(crate) struct Batcher {
+    sender: mpsc::Sender<Message>,
+    /// Shuts the task down once the last handle is dropped.
+    _shutdown: Arc<Shutdown>,
+}
+
+impl Batcher {
+    /// Spawns the background task writing to `sink`.
+    pub(crate) fn spawn<S: BatchSink>(
+        sink: S,
+        config: GoogleWriterConfig,
+        sampler: Option<Arc<Sampler>>,
+    ) -> Self {
+        let (tx, rx) = mpsc::channel::<Message>(config.buffer_size);
         let (shutdown_tx, shutdown_rx) = oneshot::channel();
-        let logger = Arc::new(RwLock::new(google_logger));
-        let handle = tokio::spawn(Self::run_batch_logger(rx, shutdown_rx, config, logger));
+        let sink = Arc::new(RwLock::new(sink));
+        let handle = tokio::spawn(Self::run(rx, shutdown_rx, config, sink, sampler));
 
         Self {
             sender: tx,
-            shutdown_trigger: Some(shutdown_tx),
-            shutdown_handle: Some(handle),
-            _marker: std::marker::PhantomData,
+            _shutdown: Arc::new(Shutdown {
+                trigger: Some(shutdown_tx),
+                handle: Some(handle),
+            }),
         }
     }
 
-    /// Background task that receives log entries, batches them, and writes them to GCP.
+    /// Queues `entry`, dropping it if the channel is full.
+    pub(crate) fn send(&self, entry: Value) {
+        if let Err(e) = self.sender.try_send(Message::Entry(entry)) {
+            tracing::warn!("Dropped log (channel full): {e}");
+        }
+    }
+
+    /// Writes the entries queued so far, returning once they are written or dropped.
+    pub(crate) async fn flush(&self) {
+        let (done_tx, done_rx) = oneshot::channel();
+        if self.sender.send(Message::Flush(done_tx)).await.is_ok() {
+            let _ = done_rx.await;
+        }
+    }
+
+    /// Background task that receives entries, batches them, and writes them to GCP.
+    ///
+    /// With sampling enabled, it also queues the suppressed events summaries every
+    /// summary interval.
     ///
-    /// This loop exits cleanly when a shutdown signal is received.
-    async fn run_batch_logger(
-        mut receiver: mpsc::Receiver<Value>,
+    /// After a fatal error (see [`LoggerError::is_fatal`]), entries are discarded until the
+    /// [`Cooldown`] ends.
+    ///
+    /// This loop exits cleanly when a shutdown signal is received, after writing the
+    /// entries still queued.
+    async fn run<S: BatchSink>(
+        mut receiver: mpsc::Receiver<Message>,
         mut shutdown: oneshot::Receiver<()>,
         config: GoogleWriterConfig,
-        logger: Arc<RwLock<GoogleLogger<M>>>,
+        sink: Arc<RwLock<S>>,
+        sampler: Option<Arc<Sampler>>,
     ) {
         let mut buffer = Vec::with_capacity(config.max_batch);
+        let mut cooldown = Cooldown::new(config.fatal_error_cooldown);
         let mut flush_deadline: Option<Pin<Box<Sleep>>> = None;
+        let mut summary_interval: Option<Interval> = sampler
+            .as_ref()
+            .and(config.sampling.as_ref())
+            .map(|sampling| {
+                let start = tokio::time::Instant::now() + sampling.interval();
+                let mut interval = tokio::time::interval_at(start, sampling.interval());
+                interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                interval
+            });
 
         loop {
             tokio::select! {
@@ -66,21 +414,32 @@ impl<M: LogMapper> GoogleWriter<M> {
                     break;
                 }
 
-                // New log entry received
-                Some(entry) = receiver.recv() => {
-                    buffer.push(entry);
+                Some(message) = receiver.recv() => match message {
+                    // New log entry received
+                    Message::Entry(_) if cooldown.is_active() => {}
+                    Message::Entry(entry) => {
+                        buffer.push(entry);
 
-                    // Start the flush timer if this is the first entry
-                    if flush_deadline.is_none() {
-                        flush_deadline = Some(Box::pin(tokio::time::sleep(config.max_delay)));
-                    }
+                        // Start the flush timer if this is the first entry
+                        if flush_deadline.is_none() {
+                            flush_deadline = Some(Box::pin(tokio::time::sleep(config.max_delay)));
+                        }
 
-                    // Flush immediately if batch size limit is hit
-                    if buffer.len() >= config.max_batch {
-                        Self::flush_batch(&logger, std::mem::take(&mut buffer)).await;
+                        // Flush immediately if batch size limit is hit
+                        if buffer.len() >= config.max_batch {
+                            Self::write(&sink, std::mem::take(&mut buffer), &mut cooldown).await;
+                            flush_deadline = None;
+                        }
+                    }
+                    // Flush requested
+                    Message::Flush(done) => {
+                        if !buffer.is_empty() {
+                            Self::write(&sink, std::mem::take(&mut buffer), &mut cooldown).await;
+                        }
                         flush_deadline = None;
+                        let _ = done.send(());
                     }
-                }
+                },
                 // Flush due to timeout
                 _ = async {
                     if let Some(deadline) = &mut flush_deadline {
@@ -88,26 +447,108 @@ impl<M: LogMapper> GoogleWriter<M> {
                     }
                 }, if flush_deadline.is_some() => {
                     if !buffer.is_empty() {
-                        Self::flush_batch(&logger, std::mem::take(&mut buffer)).await;
+                        Self::write(&sink, std::mem::take(&mut buffer), &mut cooldown).await;
                     }
                     flush_deadline = None;
                 }
+                // Report suppressed events
+                _ = async {
+                    if let Some(interval) = &mut summary_interval {
+                        interval.tick().await;
+                    }
+                }, if summary_interval.is_some() && !cooldown.is_active() => {
+                    let summaries = sampler.as_deref().map(Sampler::take_summaries);
+                    buffer.extend(summaries.into_iter().flatten());
+
+                    if !buffer.is_empty() && flush_deadline.is_none() {
+                        flush_deadline = Some(Box::pin(tokio::time::sleep(config.max_delay)));
+                    }
+                }
             }
         }
 
+        // entries queued before the shutdown signal
+        receiver.close();
+        let mut flushes = Vec::new();
+        while let Ok(message) = receiver.try_recv() {
+            match message {
+                Message::Entry(_) if cooldown.is_active() => {}
+                Message::Entry(entry) => buffer.push(entry),
+                Message::Flush(done) => flushes.push(done),
+            }
+        }
+
+        // report what was suppressed since the last summary
+        if let Some(sampler) = &sampler
+            && !cooldown.is_active()
+        {
+            buffer.extend(sampler.take_summaries());
+        }
+
         // final flush on shutdown
-        if !buffer.is_empty() {
-            Self::flush_batch(&logger, buffer).await;
+        for batch in buffer.chunks(config.max_batch.max(1)) {
+            Self::write(&sink, batch.to_vec(), &mut cooldown).await;
+        }
+        for done in flushes {
+            let _ = done.send(());
         }
 
         tracing::debug!("Background task shut down cleanly.");
     }
 
-    /// Flushes a batch of log entries to the Google Cloud Logging API.
-    async fn flush_batch(logger: &Arc<RwLock<GoogleLogger<M>>>, batch: Vec<Value>) {
-        let mut guard = logger.write().await;
-        if let Err(err) = guard.write_logs(batch).await {
-            tracing::error!("Failed to write log batch: {err}");
+    /// Flushes a batch of entries to the sink, or drops it during a cooldown.
+    async fn write<S: BatchSink>(
+        sink: &Arc<RwLock<S>>,
+        batch: Vec<Value>,
+        cooldown: &mut Cooldown,
+    ) {
+        if cooldown.is_active() {
+            return;
+        }
+        let fatal = !Self::flush_batch(sink, batch).await;
+        cooldown.record(fatal);
+    }
+
+    /// Flushes a batch of entries to the sink.
+    ///
+    /// Retryable errors (see [`LoggerError::is_retryable`]) are retried with exponential
+    /// backoff; `insertId`s keep entries of partially written batches from being duplicated.
+    /// Returns `false` after a fatal error, when later batches are unlikely to be written
+    /// either until the configuration changes.
+    async fn flush_batch<S: BatchSink>(sink: &Arc<RwLock<S>>, batch: Vec<Value>) -> bool {
+        let mut backoff = RETRY_BACKOFF;
+        let mut retries = 0;
+
+        loop {
+            // the sink is only locked for the attempt, not for the backoff in between
+            let result = sink.write().await.write_batch(batch.clone()).await;
+            let err = match result {
+                Ok(()) => return true,
+                Err(err) => err,
+            };
+
+            if err.is_fatal() {
+                let hint = match &err {
+                    LoggerError::GAuth(err) if err.is_invalid_grant() => {
+                        "check that the key is not revoked or deleted and that the system clock is in sync"
+                    }
+                    _ => "check the credential and project configuration",
+                };
+                tracing::error!(
+                    "Failed to write logs, {} entries dropped: {err}; {hint}",
+                    batch.len()
+                );
+                return false;
+            }
+            if !err.is_retryable() || retries == MAX_RETRIES {
+                tracing::error!("Failed to write log batch: {err}");
+                return true;
+            }
+
+            tracing::warn!("Failed to write log batch, retrying in {backoff:?}: {err}");
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+            retries += 1;
         }
     }
 }
@@ -115,15 +556,39 @@ impl<M: LogMapper> GoogleWriter<M> {
 impl<M: LogMapper> Write for GoogleWriter<M> {
     /// Accepts a serialized JSON log entry and queues it for sending.
     ///
-    /// If the internal channel is full, the log is dropped.
+    /// Every entry is stamped with an `insertId` (unless it already carries one) so that
+    /// Cloud Logging can deduplicate it if it ends up being sent more than once.
+    ///
+    /// Entries dropped by sampling or rate limiting are only counted, see
+    /// [`SamplingConfig`](crate::SamplingConfig). If the internal channel is full, the log
+    /// is dropped.
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        let log_entry: Value = serde_json::from_slice(buf)
+        let mut log_entry: Value = serde_json::from_slice(buf)
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
 
-        if let Err(e) = self.sender.try_send(log_entry) {
-            tracing::warn!("Dropped log (channel full): {e}");
+        let mut callsite = None;
+        if let Some(entry) = log_entry.as_object_mut() {
+            entry
+                .entry(INSERT_ID_KEY)
+                .or_insert_with(|| next_insert_id().into());
+            let severity_field = apply_captured(entry);
+            #[cfg(feature = "trace")]
+            crate::trace_export::apply_current_trace(entry);
+
+            if let Some(mut event) = self.event.take() {
+                callsite = event.callsite.take();
+                event.apply(entry, severity_field.as_deref());
+            }
+        }
+
+        if let (Some(sampler), Some(callsite)) = (&self.sampler, &callsite)
+            && !sampler.keep(callsite, &log_entry)
+        {
+            return Ok(buf.len());
         }
 
+        self.batcher.send(log_entry);
+
         Ok(buf.len())
     }
 
@@ -133,23 +598,154 @@ impl<M: LogMapper> Write for GoogleWriter<M> {
     }
 }
 
-impl<M: LogMapper> Drop for GoogleWriter<M> {
+/// Shutdown handle of the background task, shared by all handles feeding it.
+struct Shutdown {
+    trigger: Option<oneshot::Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Drop for Shutdown {
     /// Triggers shutdown of the background task and waits for it to complete.
     ///
-    /// Ensures that any buffered logs are flushed before the writer is dropped.
+    /// Ensures that any buffered logs are flushed before the last writer is dropped.
     fn drop(&mut self) {
         tracing::debug!("GoogleWriter is being dropped; shutting down.");
 
-        if let Some(shutdown_tx) = self.shutdown_trigger.take() {
+        if let Some(shutdown_tx) = self.trigger.take() {
             let _ = shutdown_tx.send(());
         }
 
-        if let Some(handle) = self.shutdown_handle.take() {
-            if let Err(err) =
-                tokio::task::block_in_place(|| tokio::runtime::Handle::current().block_on(handle))
-            {
-                tracing::error!("Shutdown task panicked: {:?}", err);
+        // on a current-thread runtime, the task finishes on its own if the runtime keeps running
+        if let Some(handle) = self.handle.take()
+            && let Some(runtime) = blocking_runtime()
+            && let Err(err) = tokio::task::block_in_place(|| runtime.block_on(handle))
+        {
+            tracing::error!("Shutdown task panicked: {:?}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::google_logger::ResponseErrorInner;
+
+    /// Fails with the queued errors, then accepts batches.
+    #[derive(Default)]
+    struct FakeSink {
+        errors: Vec<LoggerError>,
+        written: Arc<std::sync::Mutex<Vec<Vec<Value>>>>,
+    }
+
+    impl BatchSink for FakeSink {
+        async fn write_batch(&mut self, batch: Vec<Value>) -> Result<(), LoggerError> {
+            if let Some(err) = self.errors.pop() {
+                return Err(err);
             }
+            self.written.lock().unwrap().push(batch);
+            Ok(())
         }
     }
+
+    fn unavailable() -> LoggerError {
+        LoggerError::Response(ResponseErrorInner {
+            code: Some(503),
+            message: String::from("The service is currently unavailable."),
+            status: String::from("UNAVAILABLE"),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_flush_batch() {
+        let sink = Arc::new(RwLock::new(FakeSink {
+            errors: vec![unavailable()],
+            ..Default::default()
+        }));
+        let flush = tokio::spawn({
+            let sink = sink.clone();
+            async move { Batcher::flush_batch(&sink, vec![json!({ "message": "retried" })]).await }
+        });
+        // the sink is not held during the backoff
+        tokio::time::sleep(RETRY_BACKOFF / 2).await;
+        assert!(sink.try_write().is_ok());
+        assert!(flush.await.unwrap());
+        assert_eq!(sink.read().await.written.lock().unwrap().len(), 1);
+
+        let sink = Arc::new(RwLock::new(FakeSink {
+            errors: vec![LoggerError::MissingProjectId],
+            ..Default::default()
+        }));
+        assert!(!Batcher::flush_batch(&sink, vec![json!({ "message": "dropped" })]).await);
+        assert!(sink.read().await.written.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_flush_and_shutdown() {
+        let sink = FakeSink::default();
+        let written = sink.written.clone();
+        let batcher = Batcher::spawn(sink, GoogleWriterConfig::default(), None);
+
+        batcher.send(json!({ "message": "flushed" }));
+        batcher.flush().await;
+        assert_eq!(written.lock().unwrap().len(), 1);
+
+        // entries still queued at shutdown are written, in batches of `max_batch`
+        for index in 0..25 {
+            batcher.send(json!({ "index": index }));
+        }
+        drop(batcher);
+        let written = written.lock().unwrap();
+        assert_eq!(written.iter().map(Vec::len).sum::<usize>(), 26);
+        assert!(written.iter().all(|batch| batch.len() <= 10));
+    }
+
+    #[test]
+    fn test_severity_override() {
+        let context = || EventContext {
+            source_location: None,
+            attach_source_location: true,
+            callsite: None,
+            level_severity: LogSeverity::Info,
+            severity_override: true,
+        };
+        // `tracing_stackdriver` emits `DEFAULT` for invalid values
+        let severity = |raw: Option<&str>, emitted: &str| {
+            let mut entry = Map::new();
+            entry.insert(SEVERITY_FIELD.to_owned(), emitted.into());
+            context().apply(&mut entry, raw);
+            entry[SEVERITY_FIELD].clone()
+        };
+
+        assert_eq!(severity(Some("critical"), "CRITICAL"), "CRITICAL");
+        // a valid override is authoritative, even `DEFAULT`
+        assert_eq!(severity(Some("DEFAULT"), "DEFAULT"), "DEFAULT");
+        assert_eq!(severity(Some("not-a-severity"), "DEFAULT"), "INFO");
+        // without the raw value, `DEFAULT` is ambiguous
+        assert_eq!(severity(None, "CRITICAL"), "CRITICAL");
+        assert_eq!(severity(None, "DEFAULT"), "INFO");
+    }
+
+    #[tokio::test]
+    async fn test_fatal_error_cooldown() {
+        let mut cooldown = Cooldown::new(Duration::from_millis(50));
+
+        cooldown.record(true);
+        assert!(cooldown.is_active());
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(!cooldown.is_active());
+
+        // doubled while the error persists
+        cooldown.record(true);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(cooldown.is_active());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!cooldown.is_active());
+
+        // reset by a batch without a fatal error
+        cooldown.record(false);
+        assert!(!cooldown.is_active());
+        cooldown.record(true);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(!cooldown.is_active());
+    }
 }
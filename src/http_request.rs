@@ -0,0 +1,235 @@
+use serde_json::{Map, Value, json};
+
+use crate::utils::camel_case;
+
+/// Key under which `tracing_stackdriver` collects `http_request.*` fields.
+const HTTP_REQUEST_KEY: &str = "httpRequest";
+
+/// Conventional field names (OpenTelemetry semantic conventions and common aliases)
+/// for each [`HttpRequest`] member.
+///
+/// [`HttpRequest`]: https://cloud.google.com/logging/docs/reference/v2/rest/v2/LogEntry#HttpRequest
+const FIELDS: [(&str, &[&str]); 9] = [
+    ("requestMethod", &["http.method", "http.request.method"]),
+    (
+        "requestUrl",
+        &["http.url", "url.full", "http.target", "http.route"],
+    ),
+    ("status", &["http.status_code", "http.response.status_code"]),
+    ("latency", &["http.latency"]),
+    ("userAgent", &["http.user_agent", "user_agent.original"]),
+    (
+        "remoteIp",
+        &["http.client_ip", "client.address", "net.peer.ip"],
+    ),
+    (
+        "responseSize",
+        &["http.response_content_length", "http.response.body.size"],
+    ),
+    (
+        "requestSize",
+        &["http.request_content_length", "http.request.body.size"],
+    ),
+    ("protocol", &["http.flavor", "network.protocol.version"]),
+];
+
+/// Builds the entry's `httpRequest` from conventional HTTP fields.
+///
+/// Fields are looked up on the event first (where `tracing_stackdriver` camel-cases them,
+/// e.g. `http.status_code` becomes `httpStatusCode`) and then on the enclosing spans,
+/// innermost first. Event fields that were used are removed from the payload.
+///
+/// Spans created by `tower_http::trace::TraceLayer` are recognized too: their `method`,
+/// `uri` and `version` fields, together with the `status` and `latency` of the
+/// "finished processing request" event. A bare `latency` field is also picked up by any
+/// other event that carries HTTP fields.
+///
+/// Values that Cloud Logging would reject (e.g. a non-numeric status) are skipped.
+pub fn extract_http_request(log_entry: &mut Value) -> Option<Value> {
+    let spans = log_entry
+        .get("spans")
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+    let entry = log_entry.as_object_mut()?;
+
+    let mut http_request = match entry.remove(HTTP_REQUEST_KEY) {
+        Some(Value::Object(http_request)) => http_request,
+        _ => Map::new(),
+    };
+
+    for (key, names) in FIELDS {
+        if http_request.contains_key(key) {
+            continue;
+        }
+
+        let event_value = names.iter().find_map(|name| {
+            let name = camel_case(name);
+            let value = normalize(key, entry.get(&name)?)?;
+            entry.remove(&name);
+            Some(value)
+        });
+        let value = event_value.or_else(|| {
+            spans.iter().rev().find_map(|span| {
+                names
+                    .iter()
+                    .find_map(|name| span.get(*name).and_then(|value| normalize(key, value)))
+            })
+        });
+
+        if let Some(value) = value {
+            http_request.insert(key.to_owned(), value);
+        }
+    }
+
+    // a `tower_http` request span carries both `method` and `uri`
+    let tower_span = spans
+        .iter()
+        .rev()
+        .find(|span| span.get("method").is_some() && span.get("uri").is_some());
+    if let Some(span) = tower_span {
+        for (key, name) in [
+            ("requestMethod", "method"),
+            ("requestUrl", "uri"),
+            ("protocol", "version"),
+        ] {
+            if let Some(value) = span.get(name).and_then(|value| normalize(key, value)) {
+                http_request.entry(key).or_insert(value);
+            }
+        }
+    }
+
+    // bare `status` / `latency` are too generic to trust outside of a request context
+    let bare_fields: &[&str] = match (tower_span, http_request.is_empty()) {
+        (Some(_), _) => &["status", "latency"],
+        (None, false) => &["latency"],
+        (None, true) => &[],
+    };
+    for key in bare_fields {
+        if http_request.contains_key(*key) {
+            continue;
+        }
+        if let Some(value) = entry.get(*key).and_then(|value| normalize(key, value)) {
+            entry.remove(*key);
+            http_request.insert((*key).to_owned(), value);
+        }
+    }
+
+    (!http_request.is_empty()).then_some(Value::Object(http_request))
+}
+
+/// Converts a raw field value into the representation `HttpRequest` expects for `key`.
+fn normalize(key: &str, value: &Value) -> Option<Value> {
+    match key {
+        "status" => {
+            let status = match value {
+                Value::Number(number) => number.as_u64()?,
+                Value::String(string) => string.trim().parse().ok()?,
+                _ => return None,
+            };
+            (100..600).contains(&status).then(|| json!(status))
+        }
+        // int64 members are encoded as strings in JSON
+        "responseSize" | "requestSize" => match value {
+            Value::Number(number) => number.as_u64().map(|size| json!(size.to_string())),
+            Value::String(string) => string.trim().parse::<u64>().ok().map(|_| json!(string)),
+            _ => None,
+        },
+        "latency" => parse_latency(value).map(|seconds| json!(format!("{seconds:.9}s"))),
+        _ => value
+            .as_str()
+            .filter(|string| !string.is_empty())
+            .map(|string| json!(string)),
+    }
+}
+
+/// Parses a latency in seconds from `"12 ms"`-style strings (as produced by `tower_http`)
+/// or a plain number of milliseconds.
+fn parse_latency(value: &Value) -> Option<f64> {
+    if let Some(millis) = value.as_f64() {
+        return Some(millis / 1_000.0);
+    }
+
+    let value = value.as_str()?.trim();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount: f64 = amount.parse().ok()?;
+
+    let scale = match unit.trim() {
+        "s" => 1.0,
+        "" | "ms" => 1e-3,
+        "μs" | "us" | "µs" => 1e-6,
+        "ns" => 1e-9,
+        _ => return None,
+    };
+
+    Some(amount * scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conventional_fields() {
+        let mut entry = json!({
+            "message": "request served",
+            "httpMethod": "POST",
+            "httpRoute": "/users/:id",
+            "httpStatusCode": 201,
+            "httpUserAgent": "curl/8.0",
+            "latency": "1500 μs",
+        });
+
+        let http_request = extract_http_request(&mut entry).unwrap();
+
+        assert_eq!(http_request["requestMethod"], "POST");
+        assert_eq!(http_request["requestUrl"], "/users/:id");
+        assert_eq!(http_request["status"], 201);
+        assert_eq!(http_request["userAgent"], "curl/8.0");
+        assert_eq!(http_request["latency"], "0.001500000s");
+        assert_eq!(entry, json!({ "message": "request served" }));
+    }
+
+    #[test]
+    fn test_tower_http_span() {
+        let mut entry = json!({
+            "message": "finished processing request",
+            "latency": "3 ms",
+            "status": 404,
+            "spans": [
+                { "name": "request", "method": "GET", "uri": "/missing", "version": "HTTP/1.1" },
+            ],
+        });
+
+        let http_request = extract_http_request(&mut entry).unwrap();
+
+        assert_eq!(http_request["requestMethod"], "GET");
+        assert_eq!(http_request["requestUrl"], "/missing");
+        assert_eq!(http_request["protocol"], "HTTP/1.1");
+        assert_eq!(http_request["status"], 404);
+        assert_eq!(http_request["latency"], "0.003000000s");
+        assert!(entry.get("status").is_none());
+    }
+
+    #[test]
+    fn test_unrelated_fields_are_left_in_payload() {
+        let mut entry = json!({ "message": "done", "status": 200, "latency": "3 ms" });
+
+        assert!(extract_http_request(&mut entry).is_none());
+        assert_eq!(entry["status"], 200);
+        assert_eq!(entry["latency"], "3 ms");
+    }
+
+    #[test]
+    fn test_invalid_values_are_skipped() {
+        let mut entry = json!({ "httpMethod": "GET", "httpStatusCode": "teapot" });
+
+        let http_request = extract_http_request(&mut entry).unwrap();
+
+        assert!(http_request.get("status").is_none());
+        assert_eq!(entry["httpStatusCode"], "teapot");
+    }
+}
@@ -1,20 +1,68 @@
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
+
 use derive_builder::Builder;
+use gauth::GAuth;
 use google_logger::{GoogleLogger, LogMapper, LoggerError};
-use tracing_subscriber::Registry;
+use tracing_subscriber::{Layer, Registry, layer::Layered};
 
-use self::default_mapper::DefaultLogMapper;
-use self::google_writer::GoogleWriter;
+use self::google_writer::GoogleMakeWriter;
 
 mod config;
 mod default_mapper;
+mod error_capture;
+mod error_reporting;
 mod gauth;
 pub mod google_logger;
 pub mod google_writer;
+#[cfg(feature = "http")]
+mod http_request;
 mod log_entry;
+mod redaction;
+mod routing;
+mod sampling;
+mod secret;
+mod severity;
+mod span_events;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "tower")]
+mod trace_context;
+#[cfg(feature = "trace")]
+mod trace_export;
 mod utils;
 
 pub use config::GoogleWriterConfig;
-pub use utils::{extract_trace_id, get_severity};
+pub use default_mapper::DefaultLogMapper;
+pub use error_capture::ErrorCaptureLayer;
+pub use error_reporting::{ErrorReporting, REPORTED_ERROR_EVENT_TYPE};
+pub use gauth::{Impersonation, JwtSigner, SignFuture};
+pub use google_writer::FlushGuard;
+#[cfg(feature = "http")]
+pub use http_request::extract_http_request;
+pub use log_entry::Resource;
+pub use redaction::{REDACTED_LABEL, RedactionMode, Redactor};
+pub use routing::{Route, RouteMatch};
+pub use sampling::{RateLimit, SamplingConfig};
+pub use secret::SecretBytes;
+pub use severity::{InvalidSeverity, LogSeverity, SeverityMapping};
+pub use span_events::{SpanEvents, SpanEventsLayer};
+#[cfg(feature = "tower")]
+pub use trace_context::{CloudTraceLayer, CloudTraceService, TraceContext};
+#[cfg(feature = "trace")]
+pub use trace_export::TraceExportLayer;
+pub use utils::{
+    extract_insert_id, extract_span_id, extract_trace_id, extract_trace_sampled, get_severity,
+    next_insert_id, trace_resource_name,
+};
+
+/// The layer built by [`GCloudLayerConfig::build_layer`]: the `tracing_stackdriver` layer,
+/// preceded by the [`ErrorCaptureLayer`] feeding it structured errors and the optional
+/// [`SpanEventsLayer`].
+pub type GCloudLayer<M = DefaultLogMapper> = Layered<
+    tracing_stackdriver::Layer<Registry, GoogleMakeWriter<M>>,
+    Layered<Option<SpanEventsLayer<GoogleMakeWriter<M>>>, ErrorCaptureLayer, Registry>,
+    Registry,
+>;
 
 pub type DefaultGCloudLayerConfig = GCloudLayerConfig<DefaultLogMapper>;
 pub type DefaultGCloudLayerConfigBuilder = GCloudLayerConfigBuilder<DefaultLogMapper>;
@@ -31,12 +79,87 @@ pub type DefaultGCloudLayerConfigBuilder = GCloudLayerConfigBuilder<DefaultLogMa
 pub struct GCloudLayerConfig<M: LogMapper = DefaultLogMapper> {
     /// The log name shown in Cloud Logging (e.g., `"stdout"` or `"my-service"`).
     log_name: String,
-    /// Raw bytes of a Google service account JSON key.
-    logger_credential: Vec<u8>,
+    /// Raw bytes of a Google credential JSON: a service account key, `authorized_user`
+    /// credentials, or an `impersonated_service_account` or `external_account` config.
+    #[builder(default)]
+    logger_credential: SecretBytes,
+    /// Path of a credential JSON, read instead of `logger_credential`.
+    ///
+    /// The file is read again when it changes or its key is rejected, so keys rotated by a
+    /// secret manager are picked up without a restart.
+    #[builder(default)]
+    logger_credential_file: Option<PathBuf>,
+    /// Base URL of the Cloud Logging API, e.g. a Private Service Connect endpoint;
+    /// defaults to `https://logging.googleapis.com`.
+    #[builder(default)]
+    logging_endpoint: Option<String>,
+    /// Project the logs are written to; defaults to the credential's project.
+    #[builder(default)]
+    project_id: Option<String>,
+    /// Authenticate as another service account, impersonated with `logger_credential`.
+    #[builder(default)]
+    impersonation: Option<Impersonation>,
+    /// Use [self-signed JWTs](https://google.aip.dev/auth/4111) as access tokens for a
+    /// service account key, so `oauth2.googleapis.com` is never called.
+    #[builder(default)]
+    self_signed_jwt: bool,
+    /// Signs service account JWTs instead of the key's `private_key`, which may then be
+    /// left out of `logger_credential`, e.g. a signer backed by Cloud KMS.
+    #[builder(default)]
+    jwt_signer: Option<Arc<dyn JwtSigner>>,
+    /// Act on behalf of this user through domain-wide delegation; needs a service account key.
+    #[builder(default)]
+    subject: Option<String>,
+    /// OAuth scopes of the access tokens; defaults to `logging.write`.
+    #[builder(default)]
+    scopes: Vec<String>,
     #[builder(default)]
     config: GoogleWriterConfig,
     #[builder(default)]
     log_mapper: M,
+    /// Static labels attached to every entry, e.g. `env=prod` or `version=1.4.2`.
+    #[builder(default)]
+    labels: BTreeMap<String, String>,
+    /// Event or span fields promoted to entry labels, e.g. `["tenant_id"]`.
+    ///
+    /// Cloud Logging limits apply: at most 64 labels per entry, keys are truncated to
+    /// 512 bytes and values to 64 KiB.
+    #[builder(default)]
+    fields_as_labels: Vec<String>,
+    /// Routing table picking another log name, project or resource per entry.
+    ///
+    /// All routes share the layer's writer and batching pipeline.
+    #[builder(default)]
+    routes: Vec<Route>,
+    /// Redaction stage for secrets and PII, applied before the `log_mapper`.
+    #[builder(default)]
+    redactor: Option<Redactor>,
+    /// Log an entry with busy / idle durations when a span closes.
+    #[builder(default)]
+    span_events: Option<SpanEvents>,
+}
+
+impl<M: LogMapper> std::fmt::Debug for GCloudLayerConfig<M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GCloudLayerConfig")
+            .field("log_name", &self.log_name)
+            .field("logger_credential", &self.logger_credential)
+            .field("logger_credential_file", &self.logger_credential_file)
+            .field("logging_endpoint", &self.logging_endpoint)
+            .field("project_id", &self.project_id)
+            .field("impersonation", &self.impersonation)
+            .field("self_signed_jwt", &self.self_signed_jwt)
+            .field("jwt_signer", &self.jwt_signer)
+            .field("subject", &self.subject)
+            .field("scopes", &self.scopes)
+            .field("config", &self.config)
+            .field("labels", &self.labels)
+            .field("fields_as_labels", &self.fields_as_labels)
+            .field("routes", &self.routes)
+            .field("redactor", &self.redactor)
+            .field("span_events", &self.span_events)
+            .finish_non_exhaustive()
+    }
 }
 
 impl<M: LogMapper> GCloudLayerConfig<M> {
@@ -44,7 +167,16 @@ impl<M: LogMapper> GCloudLayerConfig<M> {
     ///
     /// Creates a `GoogleLogger` from the provided log name and credentials,
     /// then wraps it in a `GoogleWriter` for async batching. Returns a
-    /// `tracing_stackdriver` layer that can be added to a subscriber.
+    /// `tracing_stackdriver` layer, combined with an [`ErrorCaptureLayer`] (and a
+    /// [`SpanEventsLayer`] if `span_events` is set), that can be added to a subscriber.
+    ///
+    /// Fails if no project is known, a service account key cannot sign, no scopes are
+    /// set, or a `subject` is set for another credential type. Use
+    /// [`verify_credentials`](Self::verify_credentials) to also fetch a token.
+    ///
+    /// Entries are flushed when the layer is dropped, which never happens for a subscriber
+    /// installed with `.init()`; use [`build_layer_with_guard`](Self::build_layer_with_guard)
+    /// to flush them at exit.
     ///
     /// # Example
     /// ```no_run
@@ -64,21 +196,152 @@ impl<M: LogMapper> GCloudLayerConfig<M> {
     ///     Ok(())
     /// }
     /// ```
-    pub fn build_layer(
-        self,
-    ) -> Result<tracing_stackdriver::Layer<Registry, impl Fn() -> GoogleWriter<M>>, LoggerError>
-    {
+    pub fn build_layer(self) -> Result<GCloudLayer<M>, LoggerError> {
+        // nothing is queued yet, so dropping the guard does not flush anything
+        self.build_layer_with_guard().map(|(layer, _)| layer)
+    }
+
+    /// Like [`build_layer`](Self::build_layer), also returning a [`FlushGuard`] that writes
+    /// the queued entries when dropped.
+    ///
+    /// Nothing is logged while the layer is built, as no subscriber is installed yet; the
+    /// guard's [`identity`](FlushGuard::identity) can be logged once it is.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use tracing_gcloud_layer::DefaultGCloudLayerConfigBuilder;
+    /// use tracing_subscriber::prelude::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///     let (layer, _guard) = DefaultGCloudLayerConfigBuilder::default()
+    ///         .log_name("my-service")
+    ///         .logger_credential(std::fs::read("svc-account.json")?)
+    ///         .build()?
+    ///         .build_layer_with_guard()?;
+    ///
+    ///     tracing_subscriber::registry().with(layer).init();
+    ///     tracing::info!(identity = ?_guard.identity(), "authenticating to Google Cloud");
+    ///     tracing::info!("written before `main` returns");
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn build_layer_with_guard(self) -> Result<(GCloudLayer<M>, FlushGuard<M>), LoggerError> {
+        let logger = self.logger()?;
         let GCloudLayerConfig {
             config,
-            log_mapper,
-            log_name,
-            logger_credential,
+            span_events,
+            ..
         } = self;
 
-        let log_name = std::sync::Arc::from(log_name);
-        let logger = GoogleLogger::new(log_name, logger_credential, log_mapper)?;
+        // the writer attaches a richer `sourceLocation` itself, see `GoogleWriterConfig`
+        let layer = tracing_stackdriver::layer().with_source_location(false);
+        #[cfg(feature = "opentelemetry")]
+        let layer = layer.with_cloud_trace(tracing_stackdriver::CloudTraceConfiguration {
+            project_id: logger.context().project_id.to_string(),
+        });
+
+        let make_writer = GoogleMakeWriter::new(logger, config);
+        let guard = make_writer.flush_guard();
+        let span_events =
+            span_events.map(|span_events| SpanEventsLayer::new(span_events, make_writer.clone()));
+        let layer = layer.with_writer(make_writer);
+
+        // `dyn Error` fields are captured before `tracing_stackdriver` formats the event; the
+        // optional layer must not be outermost, or its `OFF` level hint disables all events
+        // when no other layer is installed
+        Ok((
+            ErrorCaptureLayer.and_then(span_events).and_then(layer),
+            guard,
+        ))
+    }
+
+    /// Builds a [`TraceExportLayer`] exporting spans with this config's credential (including
+    /// impersonation, signer, subject and self-signed JWTs), project, batching options and
+    /// redaction; `scopes` are replaced with `trace.append`.
+    ///
+    /// See [`TraceExportLayer`] for how it is combined with the logging layer.
+    #[cfg(feature = "trace")]
+    pub fn build_trace_layer(&self) -> Result<TraceExportLayer, LoggerError> {
+        let gauth = self.gauth(&trace_export::SCOPES)?;
+        gauth.validate()?;
+
+        let mut layer = TraceExportLayer::with_gauth(gauth, self.project_id.as_deref())?
+            .with_config(self.config.clone());
+        if let Some(redactor) = &self.redactor {
+            layer = layer.with_redactor(redactor.clone());
+        }
+
+        Ok(layer)
+    }
+
+    /// Fetches an access token with this config, so a bad key, scope or subject fails at
+    /// startup rather than at the first flush.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use tracing_gcloud_layer::DefaultGCloudLayerConfigBuilder;
+    ///
+    /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// let config = DefaultGCloudLayerConfigBuilder::default()
+    ///     .log_name("my-service")
+    ///     .logger_credential(std::fs::read("svc-account.json")?)
+    ///     .build()?;
+    ///
+    /// config.verify_credentials().await?;
+    /// let layer = config.build_layer()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn verify_credentials(&self) -> Result<(), LoggerError> {
+        self.logger()?.verify_credentials().await
+    }
+
+    /// Creates the `GAuth` of the configured credential, requesting `scopes`.
+    fn gauth(&self, scopes: &[&str]) -> Result<GAuth, LoggerError> {
+        let gauth = match &self.logger_credential_file {
+            Some(path) => GAuth::from_file(path, scopes)?,
+            None if self.logger_credential.is_empty() => {
+                return Err(LoggerError::MissingCredential);
+            }
+            None => GAuth::from_bytes(self.logger_credential.expose(), scopes),
+        };
+        let mut gauth = gauth.with_self_signed_jwt(self.self_signed_jwt);
+        if let Some(signer) = &self.jwt_signer {
+            gauth = gauth.with_signer(signer.clone());
+        }
+        if let Some(subject) = &self.subject {
+            gauth = gauth.with_subject(subject);
+        }
+        if let Some(impersonation) = &self.impersonation {
+            gauth = gauth.with_impersonation(impersonation.clone());
+        }
+
+        Ok(gauth)
+    }
+
+    /// Creates the `GoogleLogger` and checks its credential configuration.
+    fn logger(&self) -> Result<GoogleLogger<M>, LoggerError> {
+        let mut logger = GoogleLogger::with_gauth(
+            Arc::from(self.log_name.as_str()),
+            self.gauth(&google_logger::SCOPES)?,
+            self.log_mapper.clone(),
+        )?
+        .with_labels(self.labels.clone())
+        .with_fields_as_labels(self.fields_as_labels.clone())
+        .with_routes(self.routes.clone())
+        .with_scopes(&self.scopes);
+        if let Some(endpoint) = &self.logging_endpoint {
+            logger = logger.with_endpoint(endpoint);
+        }
+        if let Some(project_id) = &self.project_id {
+            logger = logger.with_project_id(project_id);
+        }
+        if let Some(redactor) = &self.redactor {
+            logger = logger.with_redactor(redactor.clone());
+        }
+        logger.validate()?;
 
-        Ok(tracing_stackdriver::layer()
-            .with_writer(move || GoogleWriter::new(logger.clone(), config.clone())))
+        Ok(logger)
     }
 }
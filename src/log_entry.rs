@@ -1,43 +1,105 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
-
-#[derive(Serialize)]
-pub struct LogEntry {
-    #[serde(rename = "logName")]
-    pub log_name: String,
-    pub resource: Resource,
-    pub severity: Value,
-    #[serde(rename = "jsonPayload")]
-    pub json_payload: Value,
-    pub timestamp: Value,
-    pub labels: Labels,
-    pub trace: Value,
-}
 
-#[derive(Serialize)]
-pub struct Labels {
-    pub context: String,
-    #[serde(rename = "requestId")]
-    pub request_id: Value,
+/// Maximum number of labels Cloud Logging accepts on a single entry.
+pub const MAX_LABELS: usize = 64;
+/// Maximum size of a label key, in bytes.
+pub const MAX_LABEL_KEY_BYTES: usize = 512;
+/// Maximum size of a label value, in bytes.
+pub const MAX_LABEL_VALUE_BYTES: usize = 64 * 1024;
+
+/// Enforces Cloud Logging's label limits: keys and values are truncated to their maximum
+/// size and labels beyond [`MAX_LABELS`] are dropped.
+///
+/// `labels` go from lowest to highest precedence: a later label replaces an earlier one
+/// with the same key, and the earliest labels are dropped first.
+pub fn limit_labels(
+    labels: impl IntoIterator<Item = (String, String)>,
+) -> BTreeMap<String, String> {
+    let labels: Vec<_> = labels.into_iter().collect();
+    let mut limited = BTreeMap::new();
+
+    for (key, value) in labels.into_iter().rev() {
+        if limited.len() >= MAX_LABELS {
+            break;
+        }
+        let key = truncate(key, MAX_LABEL_KEY_BYTES);
+        limited
+            .entry(key)
+            .or_insert_with(|| truncate(value, MAX_LABEL_VALUE_BYTES));
+    }
+
+    limited
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ResourceLabels {
-    pub project_id: String,
+/// Truncates `value` to at most `max_bytes`, on a char boundary.
+fn truncate(mut value: String, max_bytes: usize) -> String {
+    if value.len() > max_bytes {
+        let mut end = max_bytes;
+        while !value.is_char_boundary(end) {
+            end -= 1;
+        }
+        value.truncate(end);
+    }
+    value
 }
 
+/// The [monitored resource](https://cloud.google.com/logging/docs/reference/v2/rest/v2/MonitoredResource)
+/// an entry is attached to.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Resource {
-    pub labels: ResourceLabels,
+    pub labels: BTreeMap<String, String>,
     #[serde(rename = "type")]
     pub resource_type: String,
 }
 
 impl Resource {
-    pub fn new_global(project_id: String) -> Self {
+    /// Creates a resource of the given type, e.g. `cloud_run_revision` with its
+    /// `service_name`, `revision_name`, `location` and `project_id` labels.
+    pub fn new(
+        resource_type: impl Into<String>,
+        labels: impl IntoIterator<Item = (impl Into<String>, impl Into<String>)>,
+    ) -> Self {
         Resource {
-            labels: ResourceLabels { project_id },
-            resource_type: "global".to_owned(),
+            labels: labels
+                .into_iter()
+                .map(|(key, value)| (key.into(), value.into()))
+                .collect(),
+            resource_type: resource_type.into(),
         }
     }
+
+    pub fn new_global(project_id: String) -> Self {
+        Self::new("global", [("project_id", project_id)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit_labels() {
+        let labels = (0..100).map(|i| (format!("key{i:03}"), "value".to_owned()));
+        let limited = limit_labels(labels);
+
+        assert_eq!(limited.len(), MAX_LABELS);
+        assert!(!limited.contains_key("key035"));
+        assert!(limited.contains_key("key036"));
+        assert!(limited.contains_key("key099"));
+
+        // later labels win
+        let limited = limit_labels([
+            ("env".to_owned(), "prod".to_owned()),
+            ("env".to_owned(), "staging".to_owned()),
+        ]);
+        assert_eq!(limited["env"], "staging");
+
+        let limited = limit_labels([("k".repeat(600), "é".repeat(40 * 1024))]);
+        let (key, value) = limited.into_iter().next().unwrap();
+
+        assert_eq!(key.len(), MAX_LABEL_KEY_BYTES);
+        assert!(value.len() <= MAX_LABEL_VALUE_BYTES);
+    }
 }
@@ -0,0 +1,369 @@
+use regex::Regex;
+use ring::hmac;
+use serde_json::{Map, Value};
+
+use crate::{
+    SecretBytes,
+    utils::{INSERT_ID_KEY, LABELS_KEY, REPORT_LOCATION_KEY, SOURCE_LOCATION_KEY, value_to_string},
+};
+
+/// Label set on entries that had something redacted.
+pub const REDACTED_LABEL: &str = "redacted";
+/// Replacement for masked values.
+pub(crate) const MASK: &str = "[REDACTED]";
+/// Top-level fields that only carry entry metadata and are never redacted.
+const RESERVED_KEYS: [&str; 6] = [
+    "time",
+    "severity",
+    "target",
+    INSERT_ID_KEY,
+    SOURCE_LOCATION_KEY,
+    REPORT_LOCATION_KEY,
+];
+
+/// Field names denied by [`Redactor::with_default_deny_list`].
+const DEFAULT_DENY_LIST: [&str; 13] = [
+    "authorization",
+    "proxy_authorization",
+    "cookie",
+    "set_cookie",
+    "password",
+    "passwd",
+    "secret",
+    "client_secret",
+    "token",
+    "access_token",
+    "refresh_token",
+    "api_key",
+    "private_key",
+];
+
+/// How a redacted value is replaced.
+#[derive(Debug, Clone, Default)]
+pub enum RedactionMode {
+    /// Replace the value with `[REDACTED]`.
+    #[default]
+    Mask,
+    /// Replace the value with its HMAC-SHA256 under `key`, so equal values can still be
+    /// correlated.
+    ///
+    /// The key must be kept secret: anyone holding it can confirm guesses of the original
+    /// values, such as a list of email addresses.
+    Hash { key: SecretBytes },
+}
+
+/// Extra check on a scrubber match, to weed out false positives.
+type Validator = fn(&str) -> bool;
+
+#[derive(Debug, Clone)]
+struct Scrubber {
+    pattern: Regex,
+    validate: Option<Validator>,
+}
+
+/// Removes secrets and PII from entries before they are mapped and leave the process.
+///
+/// - Deny-listed fields are redacted wholesale, at any nesting depth. Names are compared
+///   ignoring case and `_` / `-` / `.`, so `api_key` also covers `apiKey` and `api-key`.
+/// - Scrubbers replace the parts of string values that match a regex.
+///
+/// Entries that had anything redacted get a `redacted: "true"` label.
+///
+/// ```no_run
+/// use tracing_gcloud_layer::Redactor;
+///
+/// fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let redactor = Redactor::new()
+///         .with_default_deny_list()
+///         .with_builtin_detectors()
+///         .deny_field("ssn")
+///         .scrub(r"\bACCT-\d{8}\b")?
+///         .hash_values(std::env::var("REDACTION_KEY")?);
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    deny_fields: Vec<String>,
+    scrubbers: Vec<Scrubber>,
+    mode: RedactionMode,
+}
+
+impl Redactor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Redacts every field named `name`, wherever it appears in the payload.
+    pub fn deny_field(mut self, name: impl AsRef<str>) -> Self {
+        self.deny_fields.push(normalize_name(name.as_ref()));
+        self
+    }
+
+    /// Denies common credential fields: `authorization`, `cookie`, `password`, `token`,
+    /// `api_key`, `private_key`, ...
+    pub fn with_default_deny_list(self) -> Self {
+        DEFAULT_DENY_LIST
+            .into_iter()
+            .fold(self, |redactor, name| redactor.deny_field(name))
+    }
+
+    /// Redacts the parts of string values matching `pattern`.
+    pub fn scrub(mut self, pattern: &str) -> Result<Self, regex::Error> {
+        self.scrubbers.push(Scrubber {
+            pattern: Regex::new(pattern)?,
+            validate: None,
+        });
+        Ok(self)
+    }
+
+    /// Adds detectors for common secrets: email addresses, payment card numbers (Luhn
+    /// checked), bearer tokens, JWTs, Google API keys, AWS access keys and PEM private keys.
+    pub fn with_builtin_detectors(mut self) -> Self {
+        let detectors: [(&str, Option<Validator>); 7] = [
+            (r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}", None),
+            (r"\b(?:\d[ -]?){12,18}\d\b", Some(luhn_valid)),
+            (r"(?i)\bbearer\s+[A-Za-z0-9._~+/-]+=*", None),
+            (
+                r"\beyJ[A-Za-z0-9_-]+\.eyJ[A-Za-z0-9_-]+\.[A-Za-z0-9_-]+",
+                None,
+            ),
+            (r"\bAIza[0-9A-Za-z_-]{35}\b", None),
+            (r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b", None),
+            (
+                r"-----BEGIN [A-Z ]*PRIVATE KEY-----[\s\S]*?-----END [A-Z ]*PRIVATE KEY-----",
+                None,
+            ),
+        ];
+
+        self.scrubbers
+            .extend(detectors.into_iter().map(|(pattern, validate)| Scrubber {
+                pattern: Regex::new(pattern).expect("built-in detector is a valid regex"),
+                validate,
+            }));
+        self
+    }
+
+    /// Replaces redacted values with their HMAC-SHA256 under `key` instead of masking them.
+    ///
+    /// Unlike a plain digest, the HMAC cannot be reversed by hashing candidate values
+    /// without the key, which must be kept secret.
+    ///
+    /// An empty key is rejected with a warning and values stay masked, as its hashes of
+    /// low-entropy values (emails, phone numbers) could be brute-forced.
+    pub fn hash_values(mut self, key: impl Into<SecretBytes>) -> Self {
+        let key = key.into();
+        if key.is_empty() {
+            tracing::warn!("Empty redaction key, redacted values are masked instead of hashed");
+            self.mode = RedactionMode::Mask;
+        } else {
+            self.mode = RedactionMode::Hash { key };
+        }
+        self
+    }
+
+    /// Redacts `log_entry` in place, returning whether anything was redacted.
+    pub fn redact(&self, log_entry: &mut Value) -> bool {
+        let Some(entry) = log_entry.as_object_mut() else {
+            return false;
+        };
+
+        let mut redacted = false;
+        for (key, value) in entry.iter_mut() {
+            if !RESERVED_KEYS.contains(&key.as_str()) {
+                redacted |= self.redact_field(key, value);
+            }
+        }
+
+        if redacted {
+            let labels = entry
+                .entry(LABELS_KEY)
+                .or_insert_with(|| Value::Object(Map::new()));
+            if let Some(labels) = labels.as_object_mut() {
+                labels.insert(REDACTED_LABEL.to_owned(), "true".into());
+            }
+        }
+
+        redacted
+    }
+
+    fn redact_field(&self, name: &str, value: &mut Value) -> bool {
+        let name = normalize_name(name);
+        if self.deny_fields.contains(&name) && !value.is_null() {
+            *value = self.replacement(&value_to_string(value)).into();
+            return true;
+        }
+
+        self.redact_value(value)
+    }
+
+    /// Redacts the denied fields and scrubbed strings of `fields`, without the entry handling
+    /// of [`redact`](Self::redact) (reserved keys, `redacted` label), e.g. span attributes.
+    pub(crate) fn redact_fields(&self, fields: &mut Map<String, Value>) -> bool {
+        fields.iter_mut().fold(false, |redacted, (key, value)| {
+            self.redact_field(key, value) | redacted
+        })
+    }
+
+    /// Redacts scrubbed strings and denied nested fields of `value`.
+    pub(crate) fn redact_value(&self, value: &mut Value) -> bool {
+        match value {
+            Value::Object(object) => self.redact_fields(object),
+            Value::Array(array) => array
+                .iter_mut()
+                .fold(false, |redacted, value| self.redact_value(value) | redacted),
+            Value::String(string) => match self.scrub_string(string) {
+                Some(scrubbed) => {
+                    *string = scrubbed;
+                    true
+                }
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Applies every scrubber to `string`, returning the scrubbed string if anything matched.
+    fn scrub_string(&self, string: &str) -> Option<String> {
+        let mut scrubbed = None::<String>;
+
+        for scrubber in &self.scrubbers {
+            let current = scrubbed.as_deref().unwrap_or(string);
+            let mut changed = false;
+            let replaced = scrubber
+                .pattern
+                .replace_all(current, |captures: &regex::Captures| {
+                    let matched = &captures[0];
+                    if scrubber.validate.is_none_or(|validate| validate(matched)) {
+                        changed = true;
+                        self.replacement(matched)
+                    } else {
+                        matched.to_owned()
+                    }
+                });
+
+            if changed {
+                scrubbed = Some(replaced.into_owned());
+            }
+        }
+
+        scrubbed
+    }
+
+    fn replacement(&self, value: &str) -> String {
+        match &self.mode {
+            RedactionMode::Mask => MASK.to_owned(),
+            RedactionMode::Hash { key } => {
+                let key = hmac::Key::new(hmac::HMAC_SHA256, key.expose());
+                let tag = hmac::sign(&key, value.as_bytes());
+                let hex: String = tag
+                    .as_ref()
+                    .iter()
+                    .map(|byte| format!("{byte:02x}"))
+                    .collect();
+                format!("[hmac-sha256:{hex}]")
+            }
+        }
+    }
+}
+
+/// Lowercases `name` and drops separators, so `api_key`, `apiKey` and `api-key` compare equal.
+fn normalize_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(c, '_' | '-' | '.'))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Luhn checksum, used to tell card numbers apart from other long digit runs.
+fn luhn_valid(candidate: &str) -> bool {
+    let digits: Vec<u32> = candidate.chars().filter_map(|c| c.to_digit(10)).collect();
+    if !(13..=19).contains(&digits.len()) {
+        return false;
+    }
+
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(index, digit)| match index % 2 {
+            0 => *digit,
+            _ if *digit * 2 > 9 => *digit * 2 - 9,
+            _ => *digit * 2,
+        })
+        .sum();
+
+    sum.is_multiple_of(10)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_deny_list_at_any_depth() {
+        let redactor = Redactor::new().with_default_deny_list();
+        let mut entry = json!({
+            "message": "calling upstream",
+            "request": { "headers": { "Authorization": "Bearer abc", "accept": "*/*" } },
+            "apiKey": "12345",
+        });
+
+        assert!(redactor.redact(&mut entry));
+        assert_eq!(entry["request"]["headers"]["Authorization"], MASK);
+        assert_eq!(entry["request"]["headers"]["accept"], "*/*");
+        assert_eq!(entry["apiKey"], MASK);
+        assert_eq!(entry[LABELS_KEY][REDACTED_LABEL], "true");
+    }
+
+    #[test]
+    fn test_builtin_detectors() {
+        let redactor = Redactor::new().with_builtin_detectors();
+        let mut entry = json!({
+            "message": "user jane@example.com paid with 4111 1111 1111 1111, order 1234567890123",
+            "spans": [{ "name": "checkout", "header": "bearer eyJhbGciOi.eyJzdWIi.c2lnbmF0dXJl" }],
+        });
+
+        assert!(redactor.redact(&mut entry));
+        assert_eq!(
+            entry["message"],
+            "user [REDACTED] paid with [REDACTED], order 1234567890123"
+        );
+        assert_eq!(entry["spans"][0]["header"], "[REDACTED]");
+    }
+
+    #[test]
+    fn test_hash_mode_and_untouched_entries() {
+        let redactor = Redactor::new().deny_field("email").hash_values("key");
+        let mut entry = json!({ "email": "jane@example.com", "time": "2024-01-01T00:00:00Z" });
+
+        assert!(redactor.redact(&mut entry));
+        let hashed = entry["email"].as_str().unwrap().to_owned();
+        assert!(hashed.starts_with("[hmac-sha256:"));
+
+        // equal values correlate under one key only
+        let mut again = json!({ "email": "jane@example.com" });
+        redactor.redact(&mut again);
+        assert_eq!(again["email"], hashed);
+        let mut other_key = json!({ "email": "jane@example.com" });
+        Redactor::new()
+            .deny_field("email")
+            .hash_values("other key")
+            .redact(&mut other_key);
+        assert_ne!(other_key["email"], hashed);
+
+        // an empty key would make the hashes guessable, so values are masked
+        let mut empty_key = json!({ "email": "jane@example.com" });
+        Redactor::new()
+            .deny_field("email")
+            .hash_values("")
+            .redact(&mut empty_key);
+        assert_eq!(empty_key["email"], MASK);
+
+        let mut clean = json!({ "message": "nothing to see" });
+        assert!(!redactor.redact(&mut clean));
+        assert!(clean.get(LABELS_KEY).is_none());
+    }
+}
@@ -0,0 +1,179 @@
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use crate::{
+    LogSeverity, get_severity, google_logger::LogContext, log_entry::Resource, utils::extract_field,
+};
+
+/// Selects which entries a [`Route`] applies to.
+#[derive(Debug, Clone)]
+pub enum RouteMatch {
+    /// The event target starts with the given prefix (e.g. `my_app::audit`).
+    TargetPrefix(String),
+    /// The entry severity is at least the given one.
+    MinSeverity(LogSeverity),
+    /// An event or span field has the given value (non-string values are compared
+    /// by their JSON representation).
+    Field { name: String, value: String },
+    /// All of the inner matchers match.
+    All(Vec<RouteMatch>),
+}
+
+impl RouteMatch {
+    pub fn matches(&self, log_entry: &Value) -> bool {
+        match self {
+            Self::TargetPrefix(prefix) => log_entry
+                .get("target")
+                .and_then(Value::as_str)
+                .is_some_and(|target| target.starts_with(prefix.as_str())),
+            Self::MinSeverity(min_severity) => get_severity(log_entry)
+                .as_str()
+                .and_then(|severity| severity.parse::<LogSeverity>().ok())
+                .is_some_and(|severity| severity >= *min_severity),
+            Self::Field { name, value } => {
+                extract_field(log_entry, name).is_some_and(|field| match field {
+                    Value::String(field) => field == *value,
+                    field => value.parse::<Value>().is_ok_and(|value| value == field),
+                })
+            }
+            Self::All(matchers) => matchers.iter().all(|matcher| matcher.matches(log_entry)),
+        }
+    }
+}
+
+/// Sends matching entries to another log, and optionally another project or resource.
+///
+/// Routes are evaluated in order and the first match wins; entries that match no route
+/// keep the layer's `log_name` and project.
+///
+/// ```
+/// use tracing_gcloud_layer::{LogSeverity, Route};
+///
+/// let routes = vec![
+///     Route::target_prefix("my_app::audit").to_log("audit"),
+///     Route::field("tenant", "acme").to_log("app").to_project("acme-logs"),
+///     Route::min_severity(LogSeverity::Error).to_log("errors"),
+/// ];
+/// ```
+#[derive(Debug, Clone)]
+pub struct Route {
+    pub matcher: RouteMatch,
+    pub log_name: Option<String>,
+    pub project_id: Option<String>,
+    pub resource: Option<Resource>,
+}
+
+impl Route {
+    pub fn new(matcher: RouteMatch) -> Self {
+        Self {
+            matcher,
+            log_name: None,
+            project_id: None,
+            resource: None,
+        }
+    }
+
+    /// Matches events whose target starts with `prefix`.
+    pub fn target_prefix(prefix: impl Into<String>) -> Self {
+        Self::new(RouteMatch::TargetPrefix(prefix.into()))
+    }
+
+    /// Matches entries with at least the given severity.
+    pub fn min_severity(severity: LogSeverity) -> Self {
+        Self::new(RouteMatch::MinSeverity(severity))
+    }
+
+    /// Matches entries where the event or span field `name` equals `value`.
+    pub fn field(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self::new(RouteMatch::Field {
+            name: name.into(),
+            value: value.into(),
+        })
+    }
+
+    /// Writes matching entries to the log `log_name`.
+    pub fn to_log(mut self, log_name: impl Into<String>) -> Self {
+        self.log_name = Some(log_name.into());
+        self
+    }
+
+    /// Writes matching entries to the project `project_id`.
+    pub fn to_project(mut self, project_id: impl Into<String>) -> Self {
+        self.project_id = Some(project_id.into());
+        self
+    }
+
+    /// Attaches matching entries to `resource` instead of the `global` resource.
+    pub fn with_resource(mut self, resource: Resource) -> Self {
+        self.resource = Some(resource);
+        self
+    }
+
+    /// Applies this route's destination to `context`.
+    pub(crate) fn apply(&self, context: &LogContext) -> LogContext {
+        let mut context = context.clone();
+        if let Some(log_name) = &self.log_name {
+            context.log_label = Arc::from(log_name.as_str());
+        }
+        if let Some(project_id) = &self.project_id {
+            context.project_id = Arc::from(project_id.as_str());
+        }
+        if let Some(resource) = &self.resource {
+            context.resource = Some(resource.clone());
+        }
+        context
+    }
+}
+
+/// Returns the destination of `log_entry`: the first matching route applied to `context`.
+pub(crate) fn route(routes: &[Route], context: &LogContext, log_entry: &Value) -> LogContext {
+    routes
+        .iter()
+        .find(|route| route.matcher.matches(log_entry))
+        .map(|route| route.apply(context))
+        .unwrap_or_else(|| context.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn context() -> LogContext {
+        LogContext {
+            log_label: Arc::from("app"),
+            project_id: Arc::from("main-project"),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_first_matching_route_wins() {
+        let routes = [
+            Route::target_prefix("my_app::audit").to_log("audit"),
+            Route::field("tenant", "acme")
+                .to_log("tenant")
+                .to_project("acme-project"),
+            Route::min_severity(LogSeverity::Error).to_log("errors"),
+        ];
+
+        let audit = json!({ "target": "my_app::audit::login", "severity": "ERROR" });
+        let tenant = json!({ "target": "my_app", "spans": [{ "tenant": "acme" }] });
+        let error = json!({ "target": "my_app", "severity": "CRITICAL" });
+        let other = json!({ "target": "my_app", "severity": "INFO" });
+
+        assert_eq!(&*route(&routes, &context(), &audit).log_label, "audit");
+
+        let tenant = route(&routes, &context(), &tenant);
+        assert_eq!(&*tenant.log_label, "tenant");
+        assert_eq!(&*tenant.project_id, "acme-project");
+
+        assert_eq!(&*route(&routes, &context(), &error).log_label, "errors");
+
+        let other = route(&routes, &context(), &other);
+        assert_eq!(&*other.log_label, "app");
+        assert_eq!(&*other.project_id, "main-project");
+    }
+}
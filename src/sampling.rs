@@ -0,0 +1,362 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_json::{Value, json};
+use tracing::{Level, Metadata, callsite::Identifier};
+
+use crate::{
+    LogSeverity, extract_trace_sampled,
+    utils::{INSERT_ID_KEY, next_insert_id},
+};
+
+const SUMMARY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A token bucket: up to `burst` events at once, refilled at `per_second`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub burst: u32,
+    pub per_second: f64,
+}
+
+impl RateLimit {
+    pub fn new(burst: u32, per_second: f64) -> Self {
+        Self { burst, per_second }
+    }
+}
+
+/// Limits how many events reach Cloud Logging.
+///
+/// Events are first sampled by level, then checked against the per-severity and the
+/// per-callsite token buckets. Dropped events are counted per callsite and reported
+/// every `summary_interval` in a `WARNING` entry such as
+/// `"120 events suppressed from callsite my_app::worker (src/worker.rs:42)"`.
+///
+/// ```
+/// use std::time::Duration;
+/// use tracing::Level;
+/// use tracing_gcloud_layer::{LogSeverity, RateLimit, SamplingConfig};
+///
+/// let sampling = SamplingConfig::default()
+///     .per_callsite(RateLimit::new(10, 1.0))
+///     .per_severity(LogSeverity::Info, RateLimit::new(500, 100.0))
+///     .sample(Level::DEBUG, 0.01)
+///     .keep_sampled_traces(true)
+///     .summary_interval(Duration::from_secs(30));
+/// ```
+#[derive(Debug, Clone)]
+pub struct SamplingConfig {
+    per_callsite: Option<RateLimit>,
+    per_severity: HashMap<LogSeverity, RateLimit>,
+    sample_rates: HashMap<Level, f64>,
+    keep_sampled_traces: bool,
+    summary_interval: Duration,
+}
+
+impl Default for SamplingConfig {
+    fn default() -> Self {
+        Self {
+            per_callsite: None,
+            per_severity: HashMap::new(),
+            sample_rates: HashMap::new(),
+            keep_sampled_traces: false,
+            summary_interval: SUMMARY_INTERVAL,
+        }
+    }
+}
+
+impl SamplingConfig {
+    /// Rate limits every callsite independently.
+    pub fn per_callsite(mut self, limit: RateLimit) -> Self {
+        self.per_callsite = Some(limit);
+        self
+    }
+
+    /// Rate limits all entries of `severity` together.
+    pub fn per_severity(mut self, severity: LogSeverity, limit: RateLimit) -> Self {
+        self.per_severity.insert(severity, limit);
+        self
+    }
+
+    /// Keeps only a `rate` fraction (`0.0..=1.0`) of the events at `level`.
+    pub fn sample(mut self, level: Level, rate: f64) -> Self {
+        self.sample_rates.insert(level, rate.clamp(0.0, 1.0));
+        self
+    }
+
+    /// Always keeps entries that belong to a sampled trace (`traceSampled`).
+    pub fn keep_sampled_traces(mut self, keep: bool) -> Self {
+        self.keep_sampled_traces = keep;
+        self
+    }
+
+    /// How often suppressed events are reported.
+    pub fn summary_interval(mut self, interval: Duration) -> Self {
+        self.summary_interval = interval;
+        self
+    }
+
+    pub(crate) fn interval(&self) -> Duration {
+        self.summary_interval
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: &RateLimit) -> Self {
+        Self {
+            tokens: f64::from(limit.burst),
+            refilled_at: Instant::now(),
+        }
+    }
+
+    fn try_take(&mut self, limit: &RateLimit) -> bool {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.per_second).min(f64::from(limit.burst));
+        self.refilled_at = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CallsiteState {
+    name: String,
+    bucket: Option<TokenBucket>,
+    suppressed: u64,
+}
+
+/// The callsite an event comes from, as seen by the [`Sampler`].
+#[derive(Debug, Clone)]
+pub(crate) struct Callsite {
+    id: Identifier,
+    level: Level,
+    name: String,
+}
+
+impl Callsite {
+    pub(crate) fn new(meta: &Metadata<'_>) -> Self {
+        let module = meta.module_path().unwrap_or(meta.target());
+        let name = match (meta.file(), meta.line()) {
+            (Some(file), Some(line)) => format!("{module} ({file}:{line})"),
+            _ => module.to_owned(),
+        };
+
+        Self {
+            id: meta.callsite(),
+            level: *meta.level(),
+            name,
+        }
+    }
+}
+
+/// Shared sampling and rate limiting state of a writer.
+#[derive(Debug)]
+pub(crate) struct Sampler {
+    config: SamplingConfig,
+    callsites: Mutex<HashMap<Identifier, CallsiteState>>,
+    severities: Mutex<HashMap<LogSeverity, TokenBucket>>,
+    rng: SystemRandom,
+}
+
+impl Sampler {
+    pub(crate) fn new(config: SamplingConfig) -> Self {
+        Self {
+            config,
+            callsites: Mutex::new(HashMap::new()),
+            severities: Mutex::new(HashMap::new()),
+            rng: SystemRandom::new(),
+        }
+    }
+
+    /// Decides whether the entry from `callsite` is kept, counting it as suppressed otherwise.
+    pub(crate) fn keep(&self, callsite: &Callsite, log_entry: &Value) -> bool {
+        if self.config.keep_sampled_traces
+            && extract_trace_sampled(log_entry)
+                .is_some_and(|sampled| sampled.as_bool() == Some(true))
+        {
+            return true;
+        }
+
+        let keep = self.sampled(&callsite.level)
+            && self.severity_allows(log_entry)
+            && self.callsite_allows(callsite);
+
+        if !keep {
+            let mut callsites = self.callsites.lock().unwrap_or_else(|e| e.into_inner());
+            let state = callsites
+                .entry(callsite.id.clone())
+                .or_insert_with(|| self.callsite_state(callsite));
+            state.suppressed += 1;
+        }
+
+        keep
+    }
+
+    /// Builds one summary entry per callsite that had events suppressed since the last call.
+    pub(crate) fn take_summaries(&self) -> Vec<Value> {
+        let mut callsites = self.callsites.lock().unwrap_or_else(|e| e.into_inner());
+        let now = chrono::Utc::now().to_rfc3339();
+
+        callsites
+            .values_mut()
+            .filter(|state| state.suppressed > 0)
+            .map(|state| {
+                let suppressed = std::mem::take(&mut state.suppressed);
+                json!({
+                    "time": now,
+                    "target": module_path!(),
+                    "severity": LogSeverity::Warning,
+                    "message": format!(
+                        "{suppressed} events suppressed from callsite {}",
+                        state.name
+                    ),
+                    "suppressed": suppressed,
+                    "callsite": state.name,
+                    INSERT_ID_KEY: next_insert_id(),
+                })
+            })
+            .collect()
+    }
+
+    fn sampled(&self, level: &Level) -> bool {
+        let Some(rate) = self.config.sample_rates.get(level) else {
+            return true;
+        };
+
+        let mut bytes = [0u8; 8];
+        if self.rng.fill(&mut bytes).is_err() {
+            return true;
+        }
+        // 53 random bits give a uniformly distributed f64 in [0, 1)
+        let random = (u64::from_le_bytes(bytes) >> 11) as f64 / (1u64 << 53) as f64;
+
+        random < *rate
+    }
+
+    fn severity_allows(&self, log_entry: &Value) -> bool {
+        let severity = crate::get_severity(log_entry)
+            .as_str()
+            .and_then(|severity| severity.parse::<LogSeverity>().ok())
+            .unwrap_or_default();
+        let Some(limit) = self.config.per_severity.get(&severity) else {
+            return true;
+        };
+
+        let mut severities = self.severities.lock().unwrap_or_else(|e| e.into_inner());
+        severities
+            .entry(severity)
+            .or_insert_with(|| TokenBucket::new(limit))
+            .try_take(limit)
+    }
+
+    fn callsite_allows(&self, callsite: &Callsite) -> bool {
+        let Some(limit) = &self.config.per_callsite else {
+            return true;
+        };
+
+        let mut callsites = self.callsites.lock().unwrap_or_else(|e| e.into_inner());
+        callsites
+            .entry(callsite.id.clone())
+            .or_insert_with(|| self.callsite_state(callsite))
+            .bucket
+            .as_mut()
+            .is_none_or(|bucket| bucket.try_take(limit))
+    }
+
+    fn callsite_state(&self, callsite: &Callsite) -> CallsiteState {
+        CallsiteState {
+            name: callsite.name.clone(),
+            bucket: self.config.per_callsite.as_ref().map(TokenBucket::new),
+            suppressed: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::callsite::Callsite as _;
+
+    use super::*;
+
+    /// A real event callsite at `level`, one per macro invocation.
+    macro_rules! callsite {
+        ($level:expr) => {
+            Callsite::new(
+                tracing::callsite!(
+                    name: "event",
+                    kind: tracing::metadata::Kind::EVENT,
+                    level: $level,
+                    fields: message
+                )
+                .metadata(),
+            )
+        };
+    }
+
+    #[test]
+    fn test_callsite_rate_limit_and_summary() {
+        let sampler = Sampler::new(SamplingConfig::default().per_callsite(RateLimit::new(2, 0.0)));
+        let entry = json!({ "severity": "INFO", "message": "tick" });
+        let callsite = callsite!(Level::INFO);
+        assert_eq!(
+            callsite.name,
+            format!("{} ({}:{})", module_path!(), file!(), line!() - 3)
+        );
+
+        let kept = (0..5).filter(|_| sampler.keep(&callsite, &entry)).count();
+        assert_eq!(kept, 2);
+
+        let summaries = sampler.take_summaries();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(
+            summaries[0]["message"],
+            format!("3 events suppressed from callsite {}", callsite.name)
+        );
+        assert_eq!(summaries[0]["severity"], "WARNING");
+        assert!(sampler.take_summaries().is_empty());
+    }
+
+    #[test]
+    fn test_sampling_keeps_sampled_traces() {
+        let sampler = Sampler::new(
+            SamplingConfig::default()
+                .sample(Level::DEBUG, 0.0)
+                .per_severity(LogSeverity::Info, RateLimit::new(1, 0.0))
+                .keep_sampled_traces(true),
+        );
+        let debug = json!({ "severity": "DEBUG" });
+        let info = json!({ "severity": "INFO" });
+        let traced = json!({ "severity": "DEBUG", "spans": [{ "trace_sampled": true }] });
+
+        let debug_callsite = callsite!(Level::DEBUG);
+        let info_callsite = callsite!(Level::INFO);
+
+        assert!(!sampler.keep(&debug_callsite, &debug));
+        assert!(sampler.keep(&debug_callsite, &traced));
+        assert!(sampler.keep(&info_callsite, &info));
+        assert!(!sampler.keep(&info_callsite, &info));
+
+        // one summary per callsite
+        let suppressed: Vec<_> = sampler
+            .take_summaries()
+            .iter()
+            .map(|summary| summary["suppressed"].as_u64().unwrap())
+            .collect();
+        assert_eq!(suppressed, [1, 1]);
+    }
+}
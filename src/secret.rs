@@ -0,0 +1,80 @@
+use std::fmt;
+
+use zeroize::Zeroize;
+
+use crate::redaction::MASK;
+
+/// Secret bytes, such as a credential JSON, zeroized when dropped.
+///
+/// `Debug` only shows the length, so secrets do not end up in logs or panic messages.
+///
+/// The secrets parsed from a credential (`private_key`, `client_secret`, `refresh_token`,
+/// including those of impersonated `source_credentials`) are zeroized when dropped as well. Other copies are not, such as access tokens or the
+/// JSON parser's transient buffers. It does not implement `PartialEq`, as a plain
+/// comparison is not constant-time.
+///
+/// ```
+/// use tracing_gcloud_layer::SecretBytes;
+///
+/// let credential = SecretBytes::from(r#"{"type":"service_account"}"#);
+/// assert_eq!(format!("{credential:?}"), "SecretBytes([REDACTED], 26 bytes)");
+/// ```
+#[derive(Clone, Default)]
+pub struct SecretBytes(Vec<u8>);
+
+impl SecretBytes {
+    /// Returns the secret bytes.
+    pub fn expose(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Drop for SecretBytes {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl fmt::Debug for SecretBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SecretBytes({MASK}, {} bytes)", self.0.len())
+    }
+}
+
+impl From<Vec<u8>> for SecretBytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<&[u8]> for SecretBytes {
+    fn from(bytes: &[u8]) -> Self {
+        Self(bytes.to_vec())
+    }
+}
+
+impl<const N: usize> From<&[u8; N]> for SecretBytes {
+    fn from(bytes: &[u8; N]) -> Self {
+        Self(bytes.to_vec())
+    }
+}
+
+impl From<String> for SecretBytes {
+    fn from(string: String) -> Self {
+        Self(string.into_bytes())
+    }
+}
+
+impl From<&str> for SecretBytes {
+    fn from(string: &str) -> Self {
+        Self(string.as_bytes().to_vec())
+    }
+}
@@ -0,0 +1,166 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::Level;
+
+/// The severity of a log entry, covering the full Cloud Logging range.
+///
+/// See [LogSeverity](https://cloud.google.com/logging/docs/reference/v2/rest/v2/LogEntry#LogSeverity).
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum LogSeverity {
+    /// The log entry has no assigned severity level.
+    #[default]
+    Default,
+    /// Debug or trace information.
+    Debug,
+    /// Routine information, such as ongoing status or performance.
+    Info,
+    /// Normal but significant events, such as start up, shut down, or a configuration change.
+    Notice,
+    /// Warning events might cause problems.
+    Warning,
+    /// Error events are likely to cause problems.
+    Error,
+    /// Critical events cause more severe problems or outages.
+    Critical,
+    /// A person must take an action immediately.
+    Alert,
+    /// One or more systems are unusable.
+    Emergency,
+}
+
+#[derive(Debug, Error)]
+#[error("invalid log severity: {0:?}")]
+pub struct InvalidSeverity(pub String);
+
+impl LogSeverity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Default => "DEFAULT",
+            Self::Debug => "DEBUG",
+            Self::Info => "INFO",
+            Self::Notice => "NOTICE",
+            Self::Warning => "WARNING",
+            Self::Error => "ERROR",
+            Self::Critical => "CRITICAL",
+            Self::Alert => "ALERT",
+            Self::Emergency => "EMERGENCY",
+        }
+    }
+}
+
+impl fmt::Display for LogSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for LogSeverity {
+    type Err = InvalidSeverity;
+
+    /// Parses a severity name case-insensitively; `trace` and `warn` are accepted as aliases.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.trim().to_ascii_uppercase().as_str() {
+            "DEFAULT" => Self::Default,
+            "DEBUG" | "TRACE" => Self::Debug,
+            "INFO" => Self::Info,
+            "NOTICE" => Self::Notice,
+            "WARNING" | "WARN" => Self::Warning,
+            "ERROR" => Self::Error,
+            "CRITICAL" => Self::Critical,
+            "ALERT" => Self::Alert,
+            "EMERGENCY" => Self::Emergency,
+            _ => return Err(InvalidSeverity(s.to_owned())),
+        })
+    }
+}
+
+impl From<&Level> for LogSeverity {
+    fn from(level: &Level) -> Self {
+        SeverityMapping::default().severity(level)
+    }
+}
+
+/// Maps `tracing` levels onto Cloud Logging severities.
+///
+/// The default mirrors `tracing_stackdriver`: `TRACE` and `DEBUG` become `DEBUG`,
+/// `WARN` becomes `WARNING`, and `INFO` / `ERROR` keep their names.
+///
+/// An event can still pick its own severity with a reserved `severity` field,
+/// e.g. `tracing::error!(severity = "CRITICAL", "disk full")`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SeverityMapping {
+    pub trace: LogSeverity,
+    pub debug: LogSeverity,
+    pub info: LogSeverity,
+    pub warn: LogSeverity,
+    pub error: LogSeverity,
+}
+
+impl SeverityMapping {
+    /// Returns the severity for `level`.
+    pub fn severity(&self, level: &Level) -> LogSeverity {
+        match *level {
+            Level::TRACE => self.trace,
+            Level::DEBUG => self.debug,
+            Level::INFO => self.info,
+            Level::WARN => self.warn,
+            Level::ERROR => self.error,
+        }
+    }
+
+    /// Overrides the severity used for `level`.
+    pub fn with(mut self, level: Level, severity: LogSeverity) -> Self {
+        match level {
+            Level::TRACE => self.trace = severity,
+            Level::DEBUG => self.debug = severity,
+            Level::INFO => self.info = severity,
+            Level::WARN => self.warn = severity,
+            Level::ERROR => self.error = severity,
+        }
+        self
+    }
+}
+
+impl Default for SeverityMapping {
+    fn default() -> Self {
+        Self {
+            trace: LogSeverity::Debug,
+            debug: LogSeverity::Debug,
+            info: LogSeverity::Info,
+            warn: LogSeverity::Warning,
+            error: LogSeverity::Error,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_severity() {
+        assert_eq!(
+            "critical".parse::<LogSeverity>().unwrap(),
+            LogSeverity::Critical
+        );
+        assert_eq!("WARN".parse::<LogSeverity>().unwrap(), LogSeverity::Warning);
+        assert!("fatal".parse::<LogSeverity>().is_err());
+        assert!(LogSeverity::Emergency > LogSeverity::Error);
+    }
+
+    #[test]
+    fn test_severity_mapping() {
+        let mapping = SeverityMapping::default()
+            .with(Level::INFO, LogSeverity::Notice)
+            .with(Level::ERROR, LogSeverity::Critical);
+
+        assert_eq!(mapping.severity(&Level::TRACE), LogSeverity::Debug);
+        assert_eq!(mapping.severity(&Level::INFO), LogSeverity::Notice);
+        assert_eq!(mapping.severity(&Level::ERROR), LogSeverity::Critical);
+    }
+}
@@ -0,0 +1,285 @@
+use std::{
+    io::Write,
+    time::{Duration, Instant},
+};
+
+use serde_json::{Map, Value, json};
+use tracing::{Subscriber, span};
+use tracing_subscriber::{
+    Layer,
+    fmt::{FormattedFields, MakeWriter, format::JsonFields},
+    layer::Context,
+    registry::{LookupSpan, SpanRef},
+};
+
+/// Opt-in log entries for closed spans.
+///
+/// Every span that lived at least `min_duration` produces an entry when it closes, with
+/// the span's own fields under `span`, its parent chain (root first) under `spans`, and
+/// its timings in milliseconds: `busyMs` (time spent inside the span), `idleMs` (time
+/// spent outside of it while open) and `durationMs` (their sum).
+///
+/// These entries go through the same writer as events, with the span's level, callsite
+/// and trace context.
+///
+/// ```
+/// use std::time::Duration;
+/// use tracing_gcloud_layer::SpanEvents;
+///
+/// // only log spans slower than 250ms
+/// let span_events = SpanEvents::new().min_duration(Duration::from_millis(250));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SpanEvents {
+    min_duration: Duration,
+}
+
+impl SpanEvents {
+    /// Logs every closed span.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only logs spans that were open for at least `min_duration`.
+    pub fn min_duration(mut self, min_duration: Duration) -> Self {
+        self.min_duration = min_duration;
+        self
+    }
+}
+
+/// Busy and idle time of an open span.
+struct Timings {
+    busy: Duration,
+    idle: Duration,
+    last: Instant,
+}
+
+/// The [`Layer`] writing [`SpanEvents`] entries to `W`.
+pub struct SpanEventsLayer<W> {
+    span_events: SpanEvents,
+    make_writer: W,
+}
+
+impl<W> SpanEventsLayer<W> {
+    pub(crate) fn new(span_events: SpanEvents, make_writer: W) -> Self {
+        Self {
+            span_events,
+            make_writer,
+        }
+    }
+}
+
+impl<S, W> Layer<S> for SpanEventsLayer<W>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+    W: for<'writer> MakeWriter<'writer> + 'static,
+{
+    fn on_new_span(&self, _attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(Timings {
+                busy: Duration::ZERO,
+                idle: Duration::ZERO,
+                last: Instant::now(),
+            });
+        }
+    }
+
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id)
+            && let Some(timings) = span.extensions_mut().get_mut::<Timings>()
+        {
+            let now = Instant::now();
+            timings.idle += now.duration_since(timings.last);
+            timings.last = now;
+        }
+    }
+
+    fn on_exit(&self, id: &span::Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id)
+            && let Some(timings) = span.extensions_mut().get_mut::<Timings>()
+        {
+            let now = Instant::now();
+            timings.busy += now.duration_since(timings.last);
+            timings.last = now;
+        }
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some((busy, idle)) = span.extensions_mut().remove::<Timings>().map(|timings| {
+            let idle = timings.idle + timings.last.elapsed();
+            (timings.busy, idle)
+        }) else {
+            return;
+        };
+        if busy + idle < self.span_events.min_duration {
+            return;
+        }
+
+        let spans: Vec<Value> = span
+            .scope()
+            .from_root()
+            .map(|span| span_json(&span))
+            .collect();
+        let entry = json!({
+            "time": chrono::Utc::now().to_rfc3339(),
+            "target": span.metadata().target(),
+            "message": format!("{} closed", span.name()),
+            "span": span_json(&span),
+            "spans": spans,
+            "busyMs": millis(busy),
+            "idleMs": millis(idle),
+            "durationMs": millis(busy + idle),
+        });
+
+        let mut writer = self.make_writer.make_writer_for(span.metadata());
+        if let Err(err) = serde_json::to_vec(&entry)
+            .map_err(std::io::Error::from)
+            .and_then(|entry| writer.write_all(&entry))
+        {
+            tracing::warn!("Failed to write span event: {err}");
+        }
+    }
+}
+
+/// Serializes a span like `tracing_stackdriver` does: its fields plus its `name`.
+fn span_json<S>(span: &SpanRef<'_, S>) -> Value
+where
+    S: for<'span> LookupSpan<'span>,
+{
+    let mut fields = span
+        .extensions()
+        .get::<FormattedFields<JsonFields>>()
+        .and_then(|fields| serde_json::from_str::<Map<String, Value>>(fields).ok())
+        .unwrap_or_default();
+    fields.insert("name".to_owned(), span.name().into());
+
+    Value::Object(fields)
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+
+    /// Collects the entries written by the layer.
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<Value>>>);
+
+    struct CaptureWriter(Capture, Vec<u8>);
+
+    impl io::Write for CaptureWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.1.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Drop for CaptureWriter {
+        fn drop(&mut self) {
+            let entry = serde_json::from_slice(&self.1).unwrap();
+            self.0.0.lock().unwrap().push(entry);
+        }
+    }
+
+    impl MakeWriter<'_> for Capture {
+        type Writer = CaptureWriter;
+
+        fn make_writer(&self) -> Self::Writer {
+            CaptureWriter(self.clone(), Vec::new())
+        }
+    }
+
+    /// Runs `f` under a subscriber with span events (and `tracing_stackdriver` recording
+    /// span fields), returning the written entries.
+    fn capture(span_events: SpanEvents, f: impl FnOnce()) -> Vec<Value> {
+        let entries = Capture::default();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_stackdriver::layer().with_writer(io::sink))
+            .with(SpanEventsLayer::new(span_events, entries.clone()));
+        tracing::subscriber::with_default(subscriber, f);
+
+        entries.0.lock().unwrap().clone()
+    }
+
+    #[test]
+    fn test_span_entry() {
+        let entries = capture(SpanEvents::new(), || {
+            let span = tracing::info_span!("checkout", order_id = 42);
+            span.in_scope(|| thread::sleep(Duration::from_millis(20)));
+            thread::sleep(Duration::from_millis(20));
+        });
+
+        assert_eq!(entries.len(), 1);
+        let entry = &entries[0];
+        assert_eq!(entry["message"], "checkout closed");
+        assert_eq!(entry["target"], module_path!());
+        assert_eq!(entry["span"], json!({ "name": "checkout", "order_id": 42 }));
+        assert!(entry["time"].is_string());
+
+        let busy = entry["busyMs"].as_f64().unwrap();
+        let idle = entry["idleMs"].as_f64().unwrap();
+        assert!(busy >= 20.0, "busy: {busy}");
+        assert!(idle >= 20.0, "idle: {idle}");
+        let duration = entry["durationMs"].as_f64().unwrap();
+        assert!(
+            (duration - (busy + idle)).abs() < 1e-6,
+            "duration: {duration}"
+        );
+    }
+
+    #[test]
+    fn test_parent_chain() {
+        let entries = capture(SpanEvents::new(), || {
+            let _request = tracing::info_span!("request", path = "/checkout").entered();
+            let _handler = tracing::info_span!("handler").entered();
+            tracing::info_span!("query").in_scope(|| {});
+        });
+
+        let names: Vec<_> = entries.iter().map(|entry| &entry["message"]).collect();
+        assert_eq!(names, ["query closed", "handler closed", "request closed"]);
+        assert_eq!(
+            entries[0]["spans"],
+            json!([
+                { "name": "request", "path": "/checkout" },
+                { "name": "handler" },
+                { "name": "query" },
+            ])
+        );
+        assert_eq!(
+            entries[2]["spans"],
+            json!([{ "name": "request", "path": "/checkout" }])
+        );
+    }
+
+    #[test]
+    fn test_min_duration() {
+        let span_events = SpanEvents::new().min_duration(Duration::from_millis(30));
+        let entries = capture(span_events, || {
+            tracing::info_span!("fast").in_scope(|| {});
+            tracing::info_span!("slow").in_scope(|| thread::sleep(Duration::from_millis(40)));
+            let _idle = tracing::info_span!("idle");
+            thread::sleep(Duration::from_millis(40));
+        });
+
+        // idle time counts towards the threshold as well
+        let names: Vec<_> = entries.iter().map(|entry| &entry["message"]).collect();
+        assert_eq!(names, ["slow closed", "idle closed"]);
+    }
+}
@@ -0,0 +1,457 @@
+use std::{
+    collections::VecDeque,
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use reqwest::StatusCode;
+use serde_json::{Map, Value, json};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+
+/// Project named by [`FakeCloudLogging::credential`].
+pub const FAKE_PROJECT_ID: &str = "fake-project";
+
+const TOKEN_PATH: &str = "/token";
+const WRITE_PATH: &str = "/v2/entries:write";
+/// Path of the Cloud Trace `batchWrite` API, for any project.
+const TRACES_PATH: (&str, &str) = ("/v2/projects/", "/traces:batchWrite");
+
+/// A failure injected into the next request of a [`FakeCloudLogging`] endpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Fault {
+    /// Responds with this HTTP status, e.g. `429` or `500`, and a matching error body.
+    Status(u16),
+    /// Rejects the entries at these indexes of an `entries.write` request with
+    /// `INVALID_ARGUMENT`, writing the others only if the request sets `partialSuccess`.
+    PartialErrors(Vec<usize>),
+    /// Responds normally after this delay.
+    Delay(Duration),
+    /// Rejects the credential with an OAuth `invalid_grant` error, as for a revoked key.
+    InvalidGrant,
+}
+
+/// A local HTTP server emulating the Cloud Logging [`entries.write`] API, the Cloud Trace
+/// [`batchWrite`] API and the OAuth token endpoint, for testing a logging setup without
+/// Google Cloud.
+///
+/// Point a layer at it with [`endpoint`](Self::endpoint) and
+/// [`credential`](Self::credential), then assert on the [`entries`](Self::entries) (and
+/// [`spans`](Self::spans)) it received. Failures are injected per request with [`fail_write`](Self::fail_write) and
+/// [`fail_token`](Self::fail_token).
+///
+/// ```
+/// use tracing_gcloud_layer::DefaultGCloudLayerConfigBuilder;
+/// use tracing_gcloud_layer::testing::{FakeCloudLogging, Fault};
+/// use tracing_subscriber::prelude::*;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let server = FakeCloudLogging::start().await?;
+///     server.fail_write(Fault::Status(503));
+///
+///     let layer = DefaultGCloudLayerConfigBuilder::default()
+///         .log_name("my-service")
+///         .logger_credential(server.credential())
+///         .logging_endpoint(server.endpoint())
+///         .build()?
+///         .build_layer()?;
+///
+///     // dropping the subscriber flushes the queued entries
+///     tracing::subscriber::with_default(tracing_subscriber::registry().with(layer), || {
+///         tracing::info!("hello");
+///     });
+///
+///     assert_eq!(server.write_requests(), 2);
+///     assert_eq!(server.entries()[0]["json_payload"]["message"], "hello");
+///     Ok(())
+/// }
+/// ```
+///
+/// [`entries.write`]: https://cloud.google.com/logging/docs/reference/v2/rest/v2/entries/write
+/// [`batchWrite`]: https://cloud.google.com/trace/docs/reference/v2/rest/v2/projects.traces/batchWrite
+#[derive(Debug)]
+pub struct FakeCloudLogging {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    server: JoinHandle<()>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    entries: Vec<Value>,
+    spans: Vec<Value>,
+    write_requests: usize,
+    token_requests: usize,
+    issued_tokens: Vec<String>,
+    write_faults: VecDeque<Fault>,
+    token_faults: VecDeque<Fault>,
+}
+
+/// A parsed HTTP request.
+struct Request {
+    method: String,
+    path: String,
+    authorization: Option<String>,
+    body: Vec<u8>,
+}
+
+impl FakeCloudLogging {
+    /// Starts the server on a free local port, serving until it is dropped.
+    pub async fn start() -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(State::default()));
+        let server = tokio::spawn(serve(listener, state.clone()));
+
+        Ok(Self {
+            addr,
+            state,
+            server,
+        })
+    }
+
+    /// Base URL to set as `logging_endpoint`, or as the endpoint of a `TraceExportLayer`.
+    pub fn endpoint(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// URL of the OAuth token endpoint, to set as the `token_uri` of other credentials.
+    pub fn token_uri(&self) -> String {
+        format!("{}{TOKEN_PATH}", self.endpoint())
+    }
+
+    /// An `authorized_user` credential in [`FAKE_PROJECT_ID`], refreshed at this server.
+    pub fn credential(&self) -> Vec<u8> {
+        json!({
+            "type": "authorized_user",
+            "client_id": "fake-client-id",
+            "client_secret": "fake-client-secret",
+            "refresh_token": "fake-refresh-token",
+            "quota_project_id": FAKE_PROJECT_ID,
+            "account": "tester@example.com",
+            "token_uri": self.token_uri(),
+        })
+        .to_string()
+        .into_bytes()
+    }
+
+    /// Injects `fault` into the next `entries.write` request; faults queue up in order.
+    pub fn fail_write(&self, fault: Fault) {
+        self.state().write_faults.push_back(fault);
+    }
+
+    /// Injects `fault` into the next token request; faults queue up in order.
+    ///
+    /// [`Fault::PartialErrors`] does not apply to tokens and is answered normally.
+    pub fn fail_token(&self, fault: Fault) {
+        self.state().token_faults.push_back(fault);
+    }
+
+    /// Entries written so far, in the order they were received.
+    pub fn entries(&self) -> Vec<Value> {
+        self.state().entries.clone()
+    }
+
+    /// Spans written to Cloud Trace so far, in the order they were received.
+    pub fn spans(&self) -> Vec<Value> {
+        self.state().spans.clone()
+    }
+
+    /// Waits until at least `count` entries are written, returning all of them, or `None`
+    /// once `timeout` has elapsed.
+    pub async fn wait_for_entries(&self, count: usize, timeout: Duration) -> Option<Vec<Value>> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let entries = self.entries();
+            if entries.len() >= count {
+                return Some(entries);
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return None;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    /// Number of `entries.write` requests received, failed ones included.
+    pub fn write_requests(&self) -> usize {
+        self.state().write_requests
+    }
+
+    /// Number of token requests received, failed ones included.
+    pub fn token_requests(&self) -> usize {
+        self.state().token_requests
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        lock(&self.state)
+    }
+}
+
+impl Drop for FakeCloudLogging {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    state
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+async fn serve(listener: TcpListener, state: Arc<Mutex<State>>) {
+    while let Ok((stream, _)) = listener.accept().await {
+        tokio::spawn(serve_connection(stream, state.clone()));
+    }
+}
+
+/// Answers the requests of a keep-alive connection until the client closes it.
+async fn serve_connection(mut stream: TcpStream, state: Arc<Mutex<State>>) {
+    let mut buf = Vec::new();
+    while let Ok(Some(request)) = read_request(&mut stream, &mut buf).await {
+        let (status, body) = match (request.method.as_str(), request.path.as_str()) {
+            ("POST", TOKEN_PATH) => token(&state).await,
+            ("POST", WRITE_PATH) => write(&state, &request).await,
+            ("POST", path) if path.starts_with(TRACES_PATH.0) && path.ends_with(TRACES_PATH.1) => {
+                write_spans(&state, &request)
+            }
+            _ => google_error(StatusCode::NOT_FOUND, "no such endpoint"),
+        };
+        let body = body.to_string();
+        let response = format!(
+            "HTTP/1.1 {} {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{body}",
+            status.as_u16(),
+            status.canonical_reason().unwrap_or_default(),
+            body.len(),
+        );
+        if stream.write_all(response.as_bytes()).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Reads the next request from `stream`, keeping bytes of the following one in `buf`.
+async fn read_request(stream: &mut TcpStream, buf: &mut Vec<u8>) -> io::Result<Option<Request>> {
+    let header_end = loop {
+        if let Some(end) = buf.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end + 4;
+        }
+        if stream.read_buf(buf).await? == 0 {
+            return Ok(None);
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).into_owned();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default().to_owned();
+    let path = request_line.next().unwrap_or_default().to_owned();
+
+    let mut content_length = 0;
+    let mut authorization = None;
+    for (name, value) in lines.filter_map(|line| line.split_once(':')) {
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = value
+                .parse()
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        } else if name.eq_ignore_ascii_case("authorization") {
+            authorization = Some(value.to_owned());
+        }
+    }
+
+    while buf.len() < header_end + content_length {
+        if stream.read_buf(buf).await? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+    }
+    let body = buf[header_end..header_end + content_length].to_vec();
+    buf.drain(..header_end + content_length);
+
+    Ok(Some(Request {
+        method,
+        path,
+        authorization,
+        body,
+    }))
+}
+
+/// The OAuth token endpoint, issuing a new access token per request.
+async fn token(state: &Mutex<State>) -> (StatusCode, Value) {
+    let fault = {
+        let mut state = lock(state);
+        state.token_requests += 1;
+        state.token_faults.pop_front()
+    };
+
+    match fault {
+        Some(Fault::Status(status)) => {
+            let status = status_code(status);
+            let error = if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+                "temporarily_unavailable"
+            } else {
+                "invalid_request"
+            };
+            return (
+                status,
+                json!({ "error": error, "error_description": "injected fault" }),
+            );
+        }
+        Some(Fault::InvalidGrant) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                json!({ "error": "invalid_grant", "error_description": "Invalid JWT Signature." }),
+            );
+        }
+        Some(Fault::Delay(delay)) => tokio::time::sleep(delay).await,
+        Some(Fault::PartialErrors(_)) | None => {}
+    }
+
+    let mut state = lock(state);
+    let access_token = format!("fake-token-{}", state.issued_tokens.len() + 1);
+    state.issued_tokens.push(access_token.clone());
+
+    (
+        StatusCode::OK,
+        json!({ "access_token": access_token, "expires_in": 3600, "token_type": "Bearer" }),
+    )
+}
+
+/// The `entries.write` API, recording the entries of accepted requests.
+async fn write(state: &Mutex<State>, request: &Request) -> (StatusCode, Value) {
+    let fault = {
+        let mut state = lock(state);
+        state.write_requests += 1;
+        state.write_faults.pop_front()
+    };
+
+    if !authorized(state, request) {
+        return google_error(StatusCode::UNAUTHORIZED, "missing or unknown access token");
+    }
+
+    let body: Map<String, Value> = match serde_json::from_slice(&request.body) {
+        Ok(body) => body,
+        Err(err) => return google_error(StatusCode::BAD_REQUEST, &err.to_string()),
+    };
+    let entries = body
+        .get("entries")
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+    // like the real API, accept both the proto (snake_case) and JSON (camelCase) names
+    let field = |object: &Map<String, Value>, snake: &str, camel: &str| {
+        object.get(snake).or_else(|| object.get(camel)).cloned()
+    };
+    if entries.iter().any(|entry| {
+        entry
+            .as_object()
+            .and_then(|entry| field(entry, "log_name", "logName"))
+            .is_none()
+    }) {
+        return google_error(StatusCode::BAD_REQUEST, "entries need a logName");
+    }
+    let partial_success =
+        field(&body, "partial_success", "partialSuccess") == Some(Value::Bool(true));
+
+    match fault {
+        Some(Fault::Status(status)) => google_error(status_code(status), "injected fault"),
+        Some(Fault::PartialErrors(failed)) => {
+            if partial_success {
+                let written = entries
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(index, _)| !failed.contains(index))
+                    .map(|(_, entry)| entry);
+                lock(state).entries.extend(written);
+            }
+            let errors: Map<String, Value> = failed
+                .iter()
+                .map(|index| {
+                    let error = json!({ "code": 3, "message": "injected fault" });
+                    (index.to_string(), error)
+                })
+                .collect();
+            let (status, mut body) = google_error(StatusCode::BAD_REQUEST, "injected fault");
+            body["error"]["details"] = json!([{
+                "@type": "type.googleapis.com/google.logging.v2.WriteLogEntriesPartialErrors",
+                "logEntryErrors": errors,
+            }]);
+            (status, body)
+        }
+        Some(Fault::Delay(delay)) => {
+            tokio::time::sleep(delay).await;
+            lock(state).entries.extend(entries);
+            (StatusCode::OK, json!({}))
+        }
+        Some(Fault::InvalidGrant) | None => {
+            lock(state).entries.extend(entries);
+            (StatusCode::OK, json!({}))
+        }
+    }
+}
+
+/// The Cloud Trace `batchWrite` API, recording the spans of accepted requests.
+fn write_spans(state: &Mutex<State>, request: &Request) -> (StatusCode, Value) {
+    if !authorized(state, request) {
+        return google_error(StatusCode::UNAUTHORIZED, "missing or unknown access token");
+    }
+
+    let body: Map<String, Value> = match serde_json::from_slice(&request.body) {
+        Ok(body) => body,
+        Err(err) => return google_error(StatusCode::BAD_REQUEST, &err.to_string()),
+    };
+    let Some(spans) = body.get("spans").and_then(Value::as_array) else {
+        return google_error(StatusCode::BAD_REQUEST, "missing spans");
+    };
+    lock(state).spans.extend(spans.iter().cloned());
+
+    (StatusCode::OK, json!({}))
+}
+
+/// Whether `request` carries an access token issued by this server.
+fn authorized(state: &Mutex<State>, request: &Request) -> bool {
+    request
+        .authorization
+        .as_deref()
+        .and_then(|authorization| authorization.strip_prefix("Bearer "))
+        .is_some_and(|token| {
+            // self-signed JWTs cannot be verified here and are accepted as they are
+            lock(state)
+                .issued_tokens
+                .iter()
+                .any(|issued| issued == token)
+                || token.split('.').count() == 3
+        })
+}
+
+fn status_code(status: u16) -> StatusCode {
+    StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// A Google API error body for `status`.
+fn google_error(status: StatusCode, message: &str) -> (StatusCode, Value) {
+    let name = match status.as_u16() {
+        400 => "INVALID_ARGUMENT",
+        401 => "UNAUTHENTICATED",
+        403 => "PERMISSION_DENIED",
+        404 => "NOT_FOUND",
+        429 => "RESOURCE_EXHAUSTED",
+        500 => "INTERNAL",
+        503 => "UNAVAILABLE",
+        504 => "DEADLINE_EXCEEDED",
+        _ => "UNKNOWN",
+    };
+    let body = json!({
+        "error": { "code": status.as_u16(), "message": message, "status": name },
+    });
+
+    (status, body)
+}
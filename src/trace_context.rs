@@ -0,0 +1,237 @@
+use std::task::{Context, Poll};
+
+use http::{HeaderMap, Request};
+use tower_layer::Layer;
+use tower_service::Service;
+use tracing::{Span, instrument::Instrumented};
+
+/// Google's legacy trace propagation header: `TRACE_ID/SPAN_ID;o=OPTIONS`.
+pub const X_CLOUD_TRACE_CONTEXT: &str = "x-cloud-trace-context";
+/// W3C Trace Context header: `VERSION-TRACE_ID-PARENT_ID-FLAGS`.
+pub const TRACEPARENT: &str = "traceparent";
+
+/// Trace context propagated by an incoming request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceContext {
+    /// 32 hex characters identifying the trace.
+    pub trace_id: String,
+    /// 16 hex characters identifying the caller's span, if any.
+    pub span_id: Option<String>,
+    /// Whether the caller sampled this trace.
+    pub sampled: bool,
+}
+
+impl TraceContext {
+    /// Reads the trace context from `traceparent`, falling back to `X-Cloud-Trace-Context`.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+
+        header(TRACEPARENT)
+            .and_then(Self::from_traceparent)
+            .or_else(|| header(X_CLOUD_TRACE_CONTEXT).and_then(Self::from_cloud_trace_context))
+    }
+
+    /// Parses a W3C `traceparent` header, e.g.
+    /// `00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01`.
+    pub fn from_traceparent(value: &str) -> Option<Self> {
+        let mut parts = value.trim().split('-');
+        let version = parts.next()?;
+        let trace_id = parts.next()?;
+        let span_id = parts.next()?;
+        let flags = parts.next()?;
+
+        // version `ff` is invalid and version `00` must not carry extra fields
+        if version.len() != 2 || version == "ff" || (version == "00" && parts.next().is_some()) {
+            return None;
+        }
+        if !is_hex_id(trace_id, 32) || !is_hex_id(span_id, 16) || flags.len() != 2 {
+            return None;
+        }
+        let flags = u8::from_str_radix(flags, 16).ok()?;
+
+        Some(Self {
+            trace_id: trace_id.to_ascii_lowercase(),
+            span_id: Some(span_id.to_ascii_lowercase()),
+            sampled: flags & 0x01 == 0x01,
+        })
+    }
+
+    /// Parses an `X-Cloud-Trace-Context` header, e.g.
+    /// `105445aa7843bc8bf206b12000100000/1;o=1`.
+    ///
+    /// The span ID in this header is a decimal number; it is converted to the 16 hex
+    /// characters Cloud Logging expects in `spanId`.
+    pub fn from_cloud_trace_context(value: &str) -> Option<Self> {
+        let (ids, options) = match value.trim().split_once(';') {
+            Some((ids, options)) => (ids, Some(options)),
+            None => (value.trim(), None),
+        };
+        let (trace_id, span_id) = match ids.split_once('/') {
+            Some((trace_id, span_id)) => (trace_id, Some(span_id)),
+            None => (ids, None),
+        };
+
+        if !is_hex_id(trace_id, 32) {
+            return None;
+        }
+        let span_id = span_id
+            .and_then(|span_id| span_id.parse::<u64>().ok())
+            .filter(|span_id| *span_id != 0)
+            .map(|span_id| format!("{span_id:016x}"));
+        let sampled = options.is_some_and(|options| options.trim() == "o=1");
+
+        Some(Self {
+            trace_id: trace_id.to_ascii_lowercase(),
+            span_id,
+            sampled,
+        })
+    }
+
+    /// Creates the span that carries this context for the duration of a request.
+    ///
+    /// The mapper turns its `trace_id`, `span_id` and `trace_sampled` fields into the
+    /// entry's `trace`, `spanId` and `traceSampled`.
+    pub fn span(&self) -> Span {
+        tracing::info_span!(
+            "trace_context",
+            trace_id = %self.trace_id,
+            span_id = self.span_id.as_deref(),
+            trace_sampled = self.sampled,
+        )
+    }
+}
+
+/// Checks for a non-zero id of exactly `len` hex characters.
+fn is_hex_id(id: &str, len: usize) -> bool {
+    id.len() == len && id.bytes().all(|b| b.is_ascii_hexdigit()) && id.bytes().any(|b| b != b'0')
+}
+
+/// A [`Layer`] that records the incoming trace context on a span around each request.
+///
+/// Works with any `tower` based server, e.g. `axum::Router::layer(CloudTraceLayer::new())`:
+///
+/// ```no_run
+/// use tower_layer::Layer;
+/// use tracing_gcloud_layer::CloudTraceLayer;
+///
+/// fn with_trace_context<S>(service: S) -> impl Sized {
+///     CloudTraceLayer::new().layer(service)
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CloudTraceLayer;
+
+impl CloudTraceLayer {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<S> Layer<S> for CloudTraceLayer {
+    type Service = CloudTraceService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CloudTraceService { inner }
+    }
+}
+
+/// Service created by [`CloudTraceLayer`].
+#[derive(Debug, Clone)]
+pub struct CloudTraceService<S> {
+    inner: S,
+}
+
+impl<S, B> Service<Request<B>> for CloudTraceService<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Instrumented<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        use tracing::Instrument;
+
+        let span = TraceContext::from_headers(request.headers())
+            .map(|context| context.span())
+            .unwrap_or_else(Span::none);
+        let _guard = span.enter();
+
+        self.inner.call(request).instrument(span.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn test_traceparent() {
+        let context = TraceContext::from_traceparent(
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+        )
+        .unwrap();
+
+        assert_eq!(context.trace_id, "0af7651916cd43dd8448eb211c80319c");
+        assert_eq!(context.span_id.as_deref(), Some("b7ad6b7169203331"));
+        assert!(context.sampled);
+
+        assert!(
+            TraceContext::from_traceparent(
+                "00-00000000000000000000000000000000-b7ad6b7169203331-01"
+            )
+            .is_none()
+        );
+        assert!(
+            TraceContext::from_traceparent(
+                "ff-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01"
+            )
+            .is_none()
+        );
+        assert!(
+            TraceContext::from_traceparent("00-0af7651916cd43dd-b7ad6b7169203331-01").is_none()
+        );
+    }
+
+    #[test]
+    fn test_cloud_trace_context() {
+        let context =
+            TraceContext::from_cloud_trace_context("105445aa7843bc8bf206b12000100000/1;o=1")
+                .unwrap();
+
+        assert_eq!(context.trace_id, "105445aa7843bc8bf206b12000100000");
+        assert_eq!(context.span_id.as_deref(), Some("0000000000000001"));
+        assert!(context.sampled);
+
+        let context =
+            TraceContext::from_cloud_trace_context("105445aa7843bc8bf206b12000100000").unwrap();
+        assert_eq!(context.span_id, None);
+        assert!(!context.sampled);
+    }
+
+    #[test]
+    fn test_traceparent_takes_precedence() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            X_CLOUD_TRACE_CONTEXT,
+            HeaderValue::from_static("105445aa7843bc8bf206b12000100000/1;o=0"),
+        );
+        headers.insert(
+            TRACEPARENT,
+            HeaderValue::from_static("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-00"),
+        );
+
+        let context = TraceContext::from_headers(&headers).unwrap();
+        assert_eq!(context.trace_id, "0af7651916cd43dd8448eb211c80319c");
+
+        headers.remove(TRACEPARENT);
+        let context = TraceContext::from_headers(&headers).unwrap();
+        assert_eq!(context.trace_id, "105445aa7843bc8bf206b12000100000");
+    }
+}
@@ -0,0 +1,648 @@
+use std::{
+    cell::RefCell,
+    fmt,
+    sync::{Arc, OnceLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use reqwest::Client;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_json::{Map, Value, json};
+use tracing::{
+    Event, Level, Subscriber,
+    field::{Field, Visit},
+    span,
+};
+use tracing_subscriber::{Layer, layer::Context, registry::LookupSpan};
+
+use crate::{
+    GoogleWriterConfig, Redactor,
+    gauth::GAuth,
+    google_logger::{LoggerError, check_response},
+    google_writer::{BatchSink, Batcher},
+    utils::{SPAN_ID_KEY, TRACE_KEY, TRACE_SAMPLED_KEY},
+};
+
+/// OAuth 2.0 scope for Cloud Trace write access.
+pub(crate) const SCOPES: [&str; 1] = ["https://www.googleapis.com/auth/trace.append"];
+/// Base URL of the Cloud Trace API.
+const TRACE_ENDPOINT: &str = "https://cloudtrace.googleapis.com";
+/// Cloud Trace limits: 32 attributes per span, 128 byte keys and display names,
+/// 256 byte attribute values.
+const MAX_ATTRIBUTES: usize = 32;
+const MAX_NAME_BYTES: usize = 128;
+const MAX_ATTRIBUTE_VALUE_BYTES: usize = 256;
+/// Span fields holding the propagated trace context, see `TraceContext::span`.
+const TRACE_ID_FIELD: &str = "trace_id";
+const SPAN_ID_FIELD: &str = "span_id";
+const TRACE_SAMPLED_FIELD: &str = "trace_sampled";
+
+thread_local! {
+    /// Trace id, span id and sampled flag of the event being formatted on this thread.
+    static CURRENT: RefCell<Option<(String, String, bool)>> = const { RefCell::new(None) };
+}
+
+/// Writes spans to Cloud Trace with the [`batchWrite`] API.
+///
+/// [`batchWrite`]: https://cloud.google.com/trace/docs/reference/v2/rest/v2/projects.traces/batchWrite
+#[derive(Debug, Clone)]
+struct TraceExporter {
+    project_id: Arc<str>,
+    endpoint: Arc<str>,
+    gauth: GAuth,
+    http_client: Client,
+}
+
+impl BatchSink for TraceExporter {
+    async fn write_batch(&mut self, spans: Vec<Value>) -> Result<(), LoggerError> {
+        let access_token = self.gauth.access_token().await?;
+        let url = format!(
+            "{}/v2/projects/{}/traces:batchWrite",
+            self.endpoint, self.project_id
+        );
+
+        let response = self
+            .http_client
+            .post(url)
+            .header("Content-Type", "application/json")
+            .header("Authorization", access_token)
+            .json(&json!({ "spans": spans }))
+            .send()
+            .await?;
+
+        check_response(response).await
+    }
+}
+
+/// A [`Layer`] exporting closed spans to Cloud Trace.
+///
+/// Each span becomes a Cloud Trace v2 span with its name, start and end time, fields as
+/// attributes, parent span id and an error status if an `ERROR` event was recorded in it.
+/// Spans are batched and sent in the background like log entries, with the same
+/// [`GoogleWriterConfig`] knobs.
+///
+/// Trace ids come from a `trace_id` span field (as recorded by `TraceContext::span`), the
+/// parent span, or are generated for new root spans. Only sampled traces are exported:
+/// propagated traces keep the caller's `trace_sampled` decision, and other traces are
+/// sampled with the [`sample_ratio`](Self::with_sample_ratio) (all of them by default).
+///
+/// Log entries of events inside spans get the same `trace` and `spanId` when this layer
+/// runs before the logging layer, and `traceSampled` when the trace is exported; with
+/// `keep_sampled_traces`, those entries bypass the rate limits, so lower the ratio to keep
+/// them in check.
+///
+/// [`GCloudLayerConfig::build_trace_layer`](crate::GCloudLayerConfig::build_trace_layer)
+/// builds the layer with the credential, project, batching and redaction of the logging
+/// layer:
+///
+/// ```no_run
+/// use tracing_gcloud_layer::DefaultGCloudLayerConfigBuilder;
+/// use tracing_subscriber::prelude::*;
+///
+/// fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let config = DefaultGCloudLayerConfigBuilder::default()
+///         .log_name("my-service")
+///         .logger_credential(std::fs::read("svc-account.json")?)
+///         .build()?;
+///     let trace_layer = config.build_trace_layer()?.with_sample_ratio(0.1);
+///     let log_layer = config.build_layer()?;
+///
+///     tracing_subscriber::registry()
+///         .with(trace_layer.and_then(log_layer))
+///         .init();
+///     Ok(())
+/// }
+/// ```
+pub struct TraceExportLayer {
+    exporter: TraceExporter,
+    config: GoogleWriterConfig,
+    redactor: Option<Redactor>,
+    sample_ratio: f64,
+    batcher: OnceLock<Batcher>,
+}
+
+impl TraceExportLayer {
+    /// Creates the layer from the raw bytes of a service account JSON key.
+    pub fn new(credential_bytes: impl AsRef<[u8]>) -> Result<Self, LoggerError> {
+        Self::with_gauth(GAuth::from_bytes(credential_bytes.as_ref(), &SCOPES), None)
+    }
+
+    /// Creates the layer exporting to `project_id`, or the project of `gauth`.
+    pub(crate) fn with_gauth(gauth: GAuth, project_id: Option<&str>) -> Result<Self, LoggerError> {
+        let project_id = match project_id {
+            Some(project_id) => project_id.to_owned(),
+            None => gauth.project_id()?.ok_or(LoggerError::MissingProjectId)?,
+        };
+
+        Ok(Self {
+            exporter: TraceExporter {
+                project_id: Arc::from(project_id),
+                endpoint: Arc::from(TRACE_ENDPOINT),
+                gauth,
+                http_client: Client::new(),
+            },
+            config: GoogleWriterConfig::default(),
+            redactor: None,
+            sample_ratio: 1.0,
+            batcher: OnceLock::new(),
+        })
+    }
+
+    /// Sends spans to the Cloud Trace API at `endpoint` instead of
+    /// `https://cloudtrace.googleapis.com`, e.g. a Private Service Connect endpoint or a
+    /// local fake.
+    pub fn with_endpoint(mut self, endpoint: impl AsRef<str>) -> Self {
+        self.exporter.endpoint = Arc::from(endpoint.as_ref().trim_end_matches('/'));
+        self
+    }
+
+    /// Redacts span attributes and error messages before they are exported.
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = Some(redactor);
+        self
+    }
+
+    /// Exports this fraction (between 0 and 1) of the traces that do not carry the caller's
+    /// sampling decision.
+    pub fn with_sample_ratio(mut self, ratio: f64) -> Self {
+        self.sample_ratio = ratio.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets the batching options; sampling options are ignored.
+    pub fn with_config(mut self, config: GoogleWriterConfig) -> Self {
+        self.config = GoogleWriterConfig {
+            sampling: None,
+            ..config
+        };
+        self
+    }
+
+    /// Decides whether a trace started here is exported.
+    fn sample(&self) -> bool {
+        if self.sample_ratio >= 1.0 {
+            return true;
+        }
+
+        let mut bytes = [0u8; 8];
+        if SystemRandom::new().fill(&mut bytes).is_err() {
+            return false;
+        }
+        // 53 random bits give a uniformly distributed f64 in [0, 1)
+        let random = (u64::from_le_bytes(bytes) >> 11) as f64 / (1u64 << 53) as f64;
+
+        random < self.sample_ratio
+    }
+
+    fn send(&self, span: Value) {
+        self.batcher
+            .get_or_init(|| Batcher::spawn(self.exporter.clone(), self.config.clone(), None))
+            .send(span);
+    }
+}
+
+/// What is recorded about an open span.
+struct SpanData {
+    trace_id: String,
+    span_id: String,
+    parent_span_id: Option<String>,
+    /// Whether the trace is exported, as decided by the caller or by the sample ratio.
+    sampled: bool,
+    start_time: DateTime<Utc>,
+    attributes: Map<String, Value>,
+    error: Option<String>,
+}
+
+impl<S> Layer<S> for TraceExportLayer
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+
+        let mut visitor = AttributeVisitor::default();
+        attrs.record(&mut visitor);
+        let mut attributes = visitor.0;
+        let propagated_trace_id = attributes
+            .remove(TRACE_ID_FIELD)
+            .and_then(|trace_id| trace_id.as_str().map(str::to_ascii_lowercase))
+            .filter(|trace_id| is_hex_id(trace_id, 32));
+        let propagated_span_id = attributes
+            .remove(SPAN_ID_FIELD)
+            .and_then(|span_id| span_id.as_str().map(str::to_ascii_lowercase))
+            .filter(|span_id| is_hex_id(span_id, 16));
+        let propagated_sampled = attributes
+            .remove(TRACE_SAMPLED_FIELD)
+            .and_then(|sampled| sampled.as_bool());
+
+        let parent = span.parent().and_then(|parent| {
+            parent
+                .extensions()
+                .get::<SpanData>()
+                .map(|data| (data.trace_id.clone(), data.span_id.clone(), data.sampled))
+        });
+        let (trace_id, parent_span_id, sampled) = match (propagated_trace_id, parent) {
+            // a propagated context starts a new subtree under the caller's span
+            (Some(trace_id), _) => (
+                trace_id,
+                propagated_span_id,
+                propagated_sampled.unwrap_or_else(|| self.sample()),
+            ),
+            (None, Some((trace_id, parent_span_id, sampled))) => {
+                (trace_id, Some(parent_span_id), sampled)
+            }
+            (None, None) => (random_hex_id::<16>(), None, self.sample()),
+        };
+
+        span.extensions_mut().insert(SpanData {
+            trace_id,
+            span_id: random_hex_id::<8>(),
+            parent_span_id,
+            sampled,
+            start_time: Utc::now(),
+            attributes,
+            error: None,
+        });
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id)
+            && let Some(data) = span.extensions_mut().get_mut::<SpanData>()
+        {
+            let mut visitor = AttributeVisitor::default();
+            values.record(&mut visitor);
+            data.attributes.extend(visitor.0);
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let span = ctx.event_span(event);
+        let ids = span.as_ref().and_then(|span| {
+            span.extensions()
+                .get::<SpanData>()
+                .map(|data| (data.trace_id.clone(), data.span_id.clone(), data.sampled))
+        });
+        CURRENT.with(|current| *current.borrow_mut() = ids);
+
+        if *event.metadata().level() == Level::ERROR
+            && let Some(span) = span
+            && let Some(data) = span.extensions_mut().get_mut::<SpanData>()
+        {
+            let mut visitor = AttributeVisitor::default();
+            event.record(&mut visitor);
+            let message = visitor.0.get("message").map(|message| match message {
+                Value::String(message) => message.clone(),
+                message => message.to_string(),
+            });
+            data.error = message.or_else(|| Some(event.metadata().name().to_owned()));
+        }
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(mut data) = span.extensions_mut().remove::<SpanData>() else {
+            return;
+        };
+        if !data.sampled {
+            return;
+        }
+        if let Some(redactor) = &self.redactor {
+            redactor.redact_fields(&mut data.attributes);
+        }
+
+        let project_id = &self.exporter.project_id;
+        let mut exported = json!({
+            "name": format!(
+                "projects/{project_id}/traces/{}/spans/{}",
+                data.trace_id, data.span_id
+            ),
+            "spanId": data.span_id,
+            "displayName": truncatable_string(span.name(), MAX_NAME_BYTES),
+            "startTime": data.start_time.to_rfc3339_opts(SecondsFormat::Nanos, true),
+            "endTime": Utc::now().to_rfc3339_opts(SecondsFormat::Nanos, true),
+            "attributes": attributes(data.attributes),
+        });
+        if let Some(parent_span_id) = data.parent_span_id {
+            exported["parentSpanId"] = parent_span_id.into();
+        }
+        if let Some(error) = data.error {
+            // google.rpc.Code.UNKNOWN
+            exported["status"] = json!({ "code": 2, "message": error });
+            if let Some(redactor) = &self.redactor {
+                redactor.redact_value(&mut exported["status"]["message"]);
+            }
+        }
+
+        self.send(exported);
+    }
+}
+
+/// Adds the trace and span id (and whether the trace is exported) of the current span to a
+/// serialized log entry, unless it already carries a trace.
+pub(crate) fn apply_current_trace(entry: &mut Map<String, Value>) {
+    let current = CURRENT.with(|current| current.borrow_mut().take());
+
+    if let Some((trace_id, span_id, sampled)) = current
+        && !entry.contains_key(TRACE_KEY)
+    {
+        entry.insert(TRACE_KEY.to_owned(), trace_id.into());
+        entry.insert(SPAN_ID_KEY.to_owned(), span_id.into());
+        if sampled {
+            entry.insert(TRACE_SAMPLED_KEY.to_owned(), true.into());
+        }
+    }
+}
+
+#[derive(Default)]
+struct AttributeVisitor(Map<String, Value>);
+
+impl Visit for AttributeVisitor {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_owned(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_owned(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_owned(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_owned(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_owned(), format!("{value:?}").into());
+    }
+}
+
+/// Builds a Cloud Trace `Attributes` object, keeping the first 32 attributes.
+fn attributes(fields: Map<String, Value>) -> Value {
+    let dropped = fields.len().saturating_sub(MAX_ATTRIBUTES);
+    let attribute_map: Map<String, Value> = fields
+        .into_iter()
+        .take(MAX_ATTRIBUTES)
+        .map(|(key, value)| {
+            let value = match value {
+                Value::Bool(value) => json!({ "boolValue": value }),
+                // int64 is encoded as a string in JSON
+                Value::Number(value) if value.is_i64() || value.is_u64() => {
+                    json!({ "intValue": value.to_string() })
+                }
+                Value::String(value) => json!({
+                    "stringValue": truncatable_string(&value, MAX_ATTRIBUTE_VALUE_BYTES)
+                }),
+                value => json!({
+                    "stringValue": truncatable_string(&value.to_string(), MAX_ATTRIBUTE_VALUE_BYTES)
+                }),
+            };
+            (truncate(&key, MAX_NAME_BYTES).to_owned(), value)
+        })
+        .collect();
+
+    json!({
+        "attributeMap": attribute_map,
+        "droppedAttributesCount": dropped,
+    })
+}
+
+/// Builds a Cloud Trace `TruncatableString` of at most `max_bytes`.
+fn truncatable_string(value: &str, max_bytes: usize) -> Value {
+    let truncated = truncate(value, max_bytes);
+    json!({
+        "value": truncated,
+        "truncatedByteCount": value.len() - truncated.len(),
+    })
+}
+
+/// Truncates `value` to at most `max_bytes`, on a char boundary.
+fn truncate(value: &str, max_bytes: usize) -> &str {
+    let mut end = value.len().min(max_bytes);
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    &value[..end]
+}
+
+/// Returns a random, non-zero id of `N` bytes as lowercase hex.
+fn random_hex_id<const N: usize>() -> String {
+    let mut bytes = [0u8; N];
+    if SystemRandom::new().fill(&mut bytes).is_err() || bytes.iter().all(|b| *b == 0) {
+        // fall back to the clock, which is still unique enough for ids
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos()
+            .to_be_bytes();
+        bytes.copy_from_slice(&nanos[nanos.len() - N..]);
+        bytes[N - 1] |= 1;
+    }
+
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Checks for a non-zero id of exactly `len` hex characters.
+fn is_hex_id(id: &str, len: usize) -> bool {
+    id.len() == len && id.bytes().all(|b| b.is_ascii_hexdigit()) && id.bytes().any(|b| b != b'0')
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+
+    const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+    const SPAN_ID: &str = "00f067aa0ba902b7";
+
+    /// Collects the exported spans.
+    #[derive(Clone, Default)]
+    struct CaptureSink(Arc<Mutex<Vec<Value>>>);
+
+    impl BatchSink for CaptureSink {
+        async fn write_batch(&mut self, spans: Vec<Value>) -> Result<(), LoggerError> {
+            self.0.lock().unwrap().extend(spans);
+            Ok(())
+        }
+    }
+
+    /// Stamps every event like the logging layer does, collecting the results.
+    #[derive(Clone, Default)]
+    struct EntryLayer(Arc<Mutex<Vec<Map<String, Value>>>>);
+
+    impl<S: Subscriber> Layer<S> for EntryLayer {
+        fn on_event(&self, _event: &Event<'_>, _ctx: Context<'_, S>) {
+            let mut entry = Map::new();
+            apply_current_trace(&mut entry);
+            self.0.lock().unwrap().push(entry);
+        }
+    }
+
+    fn trace_layer() -> TraceExportLayer {
+        TraceExportLayer::new(include_bytes!("../test_fixtures/service-account-key.json")).unwrap()
+    }
+
+    /// Runs `f` under the default [`TraceExportLayer`], returning the stamped log entries
+    /// and the exported spans.
+    fn capture(f: impl FnOnce()) -> (Vec<Map<String, Value>>, Vec<Value>) {
+        capture_with(trace_layer(), f)
+    }
+
+    /// Runs `f` under `layer`, returning the stamped log entries and the exported spans.
+    fn capture_with(
+        layer: TraceExportLayer,
+        f: impl FnOnce(),
+    ) -> (Vec<Map<String, Value>>, Vec<Value>) {
+        let sink = CaptureSink::default();
+        let entries = EntryLayer::default();
+        let _ = layer
+            .batcher
+            .set(Batcher::spawn(sink.clone(), layer.config.clone(), None));
+
+        // dropping the subscriber flushes the exported spans
+        let subscriber = tracing_subscriber::registry()
+            .with(layer)
+            .with(entries.clone());
+        tracing::subscriber::with_default(subscriber, f);
+
+        let entries = entries.0.lock().unwrap().clone();
+        let spans = sink.0.lock().unwrap().clone();
+        (entries, spans)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_propagated_sampled_trace() {
+        let (entries, spans) = capture(|| {
+            let _request = tracing::info_span!(
+                "request",
+                trace_id = TRACE_ID,
+                span_id = SPAN_ID,
+                trace_sampled = true
+            )
+            .entered();
+            let _handler = tracing::info_span!("handler").entered();
+            tracing::info!("handled");
+        });
+
+        let entry = &entries[0];
+        assert_eq!(entry[TRACE_KEY], TRACE_ID);
+        assert_eq!(entry[TRACE_SAMPLED_KEY], true);
+
+        // the handler span is exported under the request span, itself under the caller's
+        let (handler, request) = (&spans[0], &spans[1]);
+        assert_eq!(entry[SPAN_ID_KEY], handler["spanId"]);
+        assert_eq!(handler["parentSpanId"], request["spanId"]);
+        assert_eq!(request["parentSpanId"], SPAN_ID);
+        assert!(
+            request["attributes"]["attributeMap"]
+                .as_object()
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_unsampled_traces() {
+        let (entries, spans) = capture(|| {
+            tracing::info_span!(
+                "request",
+                trace_id = TRACE_ID,
+                span_id = SPAN_ID,
+                trace_sampled = false
+            )
+            .in_scope(|| tracing::info!("not sampled by the caller"));
+            tracing::info_span!("job").in_scope(|| tracing::info!("started here"));
+        });
+
+        // the caller's decision is kept, traces started here are all sampled by default
+        assert_eq!(entries[0][TRACE_KEY], TRACE_ID);
+        assert!(!entries[0].contains_key(TRACE_SAMPLED_KEY));
+        assert!(is_hex_id(entries[1][TRACE_KEY].as_str().unwrap(), 32));
+        assert_eq!(entries[1][TRACE_SAMPLED_KEY], true);
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0]["displayName"]["value"], "job");
+        assert!(spans[0].get("parentSpanId").is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_sample_ratio() {
+        let (entries, spans) = capture_with(trace_layer().with_sample_ratio(0.0), || {
+            tracing::info_span!("job").in_scope(|| {
+                tracing::info_span!("step").in_scope(|| tracing::info!("not sampled"));
+            });
+            tracing::info_span!("request", trace_id = TRACE_ID, trace_sampled = true)
+                .in_scope(|| tracing::info!("sampled by the caller"));
+        });
+
+        // unsampled traces still share their ids with log entries
+        assert!(is_hex_id(entries[0][TRACE_KEY].as_str().unwrap(), 32));
+        assert!(!entries[0].contains_key(TRACE_SAMPLED_KEY));
+        assert_eq!(entries[1][TRACE_SAMPLED_KEY], true);
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0]["displayName"]["value"], "request");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_redaction() {
+        let redactor = Redactor::new()
+            .deny_field("password")
+            .scrub(r"\d{4}-\d{4}")
+            .unwrap();
+        let (_, spans) = capture_with(trace_layer().with_redactor(redactor), || {
+            tracing::info_span!("login", user = "jane", password = "hunter2").in_scope(|| {
+                tracing::error!("card 1234-5678 declined");
+            });
+        });
+
+        let attributes = &spans[0]["attributes"]["attributeMap"];
+        assert_eq!(attributes["user"]["stringValue"]["value"], "jane");
+        assert_eq!(attributes["password"]["stringValue"]["value"], "[REDACTED]");
+        assert_eq!(spans[0]["status"]["message"], "card [REDACTED] declined");
+    }
+
+    #[test]
+    fn test_attributes() {
+        let fields = json!({
+            "user_id": 42,
+            "cached": true,
+            "route": "/orders/{id}",
+            "ratio": 0.5,
+        });
+
+        let attributes = attributes(fields.as_object().unwrap().clone());
+        let map = &attributes["attributeMap"];
+
+        assert_eq!(map["user_id"]["intValue"], "42");
+        assert_eq!(map["cached"]["boolValue"], true);
+        assert_eq!(map["route"]["stringValue"]["value"], "/orders/{id}");
+        assert_eq!(map["ratio"]["stringValue"]["value"], "0.5");
+        assert_eq!(attributes["droppedAttributesCount"], 0);
+    }
+
+    #[test]
+    fn test_truncatable_string() {
+        let value = truncatable_string(&"é".repeat(100), 127);
+
+        assert_eq!(value["value"].as_str().unwrap().len(), 126);
+        assert_eq!(value["truncatedByteCount"], 74);
+    }
+
+    #[test]
+    fn test_random_ids() {
+        let trace_id = random_hex_id::<16>();
+
+        assert!(is_hex_id(&trace_id, 32));
+        assert!(is_hex_id(&random_hex_id::<8>(), 16));
+        assert_ne!(trace_id, random_hex_id::<16>());
+    }
+}
@@ -1,25 +1,242 @@
-use std::time::{SystemTime, SystemTimeError};
+use std::{
+    sync::{
+        OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{SystemTime, SystemTimeError},
+};
 
+use ring::rand::{SecureRandom, SystemRandom};
 use serde_json::Value;
 
+use crate::LogSeverity;
+
+/// Reserved event field that overrides the severity derived from the level.
+pub(crate) const SEVERITY_FIELD: &str = "severity";
+/// Special field carrying the Cloud Logging `insertId` of an entry.
+pub(crate) const INSERT_ID_KEY: &str = "logging.googleapis.com/insertId";
+/// Special field carrying the Cloud Logging `sourceLocation` of an entry.
+pub(crate) const SOURCE_LOCATION_KEY: &str = "logging.googleapis.com/sourceLocation";
+/// Callsite of an entry whose `sourceLocation` is disabled, only used as the Error
+/// Reporting `reportLocation`.
+pub(crate) const REPORT_LOCATION_KEY: &str = "tracing_gcloud_layer/reportLocation";
+/// Special field `tracing_stackdriver` collects `labels.*` event fields under.
+pub(crate) const LABELS_KEY: &str = "logging.googleapis.com/labels";
+/// Special fields `tracing_stackdriver` fills from the OpenTelemetry span context.
+pub(crate) const TRACE_KEY: &str = "logging.googleapis.com/trace";
+pub(crate) const SPAN_ID_KEY: &str = "logging.googleapis.com/spanId";
+pub(crate) const TRACE_SAMPLED_KEY: &str = "logging.googleapis.com/trace_sampled";
+
+static INSERT_ID_PREFIX: OnceLock<String> = OnceLock::new();
+static INSERT_ID_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the entry severity, or `DEFAULT` when it is missing or not a valid [`LogSeverity`].
 #[inline]
 pub fn get_severity(log_entry: &Value) -> Value {
     log_entry
-        .get("severity")
-        .cloned()
-        .unwrap_or_else(|| "DEFAULT".into())
+        .get(SEVERITY_FIELD)
+        .and_then(Value::as_str)
+        .and_then(|severity| severity.parse::<LogSeverity>().ok())
+        .unwrap_or_default()
+        .as_str()
+        .into()
 }
 
+/// Returns the trace id of the entry.
+///
+/// The OpenTelemetry span context wins; otherwise the `trace_id` recorded on the
+/// innermost span that has one is used.
 pub fn extract_trace_id(log_entry: &Value) -> Option<Value> {
+    log_entry
+        .get(TRACE_KEY)
+        .and_then(Value::as_str)
+        .map(|trace| {
+            trace
+                .rsplit_once("/traces/")
+                .map_or(trace, |(_, id)| id)
+                .into()
+        })
+        .or_else(|| extract_span_field(log_entry, "trace_id"))
+}
+
+/// Returns the span id of the entry, from the OpenTelemetry span context or a `span_id` span field.
+pub fn extract_span_id(log_entry: &Value) -> Option<Value> {
+    log_entry
+        .get(SPAN_ID_KEY)
+        .cloned()
+        .or_else(|| extract_span_field(log_entry, "span_id"))
+}
+
+/// Returns whether the trace was sampled, from the OpenTelemetry span context or a
+/// `trace_sampled` span field.
+pub fn extract_trace_sampled(log_entry: &Value) -> Option<Value> {
+    log_entry
+        .get(TRACE_SAMPLED_KEY)
+        .cloned()
+        .or_else(|| extract_span_field(log_entry, "trace_sampled"))
+}
+
+/// Looks `field` up on the event itself (under the camel-cased name `tracing_stackdriver`
+/// gives it), then on the enclosing spans.
+pub(crate) fn extract_field(log_entry: &Value, field: &str) -> Option<Value> {
+    log_entry
+        .get(camel_case(field))
+        .or_else(|| log_entry.get(field))
+        .filter(|v| !v.is_null())
+        .cloned()
+        .or_else(|| extract_span_field(log_entry, field))
+}
+
+/// Looks `field` up on the current span, then on its ancestors from the innermost out.
+fn extract_span_field(log_entry: &Value, field: &str) -> Option<Value> {
     log_entry
         .get("span")
-        .and_then(|v| v.get("trace_id"))
+        .and_then(|v| v.get(field))
+        .or_else(|| {
+            log_entry
+                .get("spans")
+                .and_then(Value::as_array)?
+                .iter()
+                .rev()
+                .find_map(|span| span.get(field))
+        })
+        .filter(|v| !v.is_null())
         .cloned()
 }
 
+/// Formats a trace id as the `projects/{project}/traces/{id}` resource name Cloud Logging expects.
+pub fn trace_resource_name(project_id: &str, trace_id: &Value) -> Value {
+    match trace_id.as_str() {
+        Some(trace_id) if trace_id.starts_with("projects/") => trace_id.into(),
+        Some(trace_id) => format!("projects/{project_id}/traces/{trace_id}").into(),
+        None => trace_id.clone(),
+    }
+}
+
+/// Returns the `insertId` assigned to the entry when it entered the writer.
+pub fn extract_insert_id(log_entry: &Value) -> Option<Value> {
+    log_entry.get(INSERT_ID_KEY).cloned()
+}
+
+/// Generates a new `insertId`: a process-unique prefix followed by a monotonic counter.
+///
+/// The prefix is random, so ids from different processes (or hosts) never collide.
+pub fn next_insert_id() -> String {
+    let prefix = INSERT_ID_PREFIX.get_or_init(|| {
+        let mut bytes = [0u8; 8];
+        if SystemRandom::new().fill(&mut bytes).is_err() {
+            // fall back to something that is still unique enough per host
+            bytes = ((u64::from(std::process::id()) << 32) ^ timestamp().unwrap_or_default())
+                .to_be_bytes();
+        }
+        bytes.iter().map(|b| format!("{b:02x}")).collect()
+    });
+    let counter = INSERT_ID_COUNTER.fetch_add(1, Ordering::Relaxed);
+
+    format!("{prefix}-{counter:016x}")
+}
+
+/// Renders a JSON value as text, without quoting strings.
+pub(crate) fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Mirrors how `tracing_stackdriver` names event fields (`http.status_code` -> `httpStatusCode`).
+pub(crate) fn camel_case(name: &str) -> String {
+    let mut camel = String::with_capacity(name.len());
+    for (index, segment) in name
+        .split(['.', '_'])
+        .filter(|segment| !segment.is_empty())
+        .enumerate()
+    {
+        let mut chars = segment.chars();
+        if let Some(first) = chars.next() {
+            if index == 0 {
+                camel.extend(first.to_lowercase());
+            } else {
+                camel.extend(first.to_uppercase());
+            }
+            camel.push_str(chars.as_str());
+        }
+    }
+    camel
+}
+
 #[inline]
 pub fn timestamp() -> Result<u64, SystemTimeError> {
     Ok(SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)?
         .as_secs())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_trace_id_from_ancestor_span() {
+        let entry = serde_json::json!({
+            "span": { "name": "db_query" },
+            "spans": [
+                { "name": "trace_context", "trace_id": "abc", "trace_sampled": true },
+                { "name": "db_query" },
+            ],
+        });
+
+        assert_eq!(extract_trace_id(&entry), Some("abc".into()));
+        assert_eq!(extract_trace_sampled(&entry), Some(true.into()));
+        assert_eq!(extract_span_id(&entry), None);
+    }
+
+    #[test]
+    fn test_opentelemetry_context_takes_precedence() {
+        let entry = serde_json::json!({
+            "logging.googleapis.com/trace": "projects/my-project/traces/4bf92f3577b34da6a3ce929d0e0e4736",
+            "logging.googleapis.com/spanId": "00f067aa0ba902b7",
+            "logging.googleapis.com/trace_sampled": true,
+            "span": { "name": "handler", "trace_id": "abc", "span_id": "def" },
+        });
+
+        assert_eq!(
+            extract_trace_id(&entry),
+            Some("4bf92f3577b34da6a3ce929d0e0e4736".into())
+        );
+        assert_eq!(extract_span_id(&entry), Some("00f067aa0ba902b7".into()));
+        assert_eq!(extract_trace_sampled(&entry), Some(true.into()));
+    }
+
+    #[test]
+    fn test_trace_resource_name() {
+        assert_eq!(
+            trace_resource_name("my-project", &"abc".into()),
+            "projects/my-project/traces/abc"
+        );
+        assert_eq!(
+            trace_resource_name("my-project", &"projects/other/traces/abc".into()),
+            "projects/other/traces/abc"
+        );
+    }
+
+    #[test]
+    fn test_camel_case() {
+        assert_eq!(camel_case("http.status_code"), "httpStatusCode");
+        assert_eq!(camel_case("latency"), "latency");
+        assert_eq!(camel_case("user_agent.original"), "userAgentOriginal");
+    }
+
+    #[test]
+    fn test_insert_ids_are_unique_and_ordered() {
+        let first = next_insert_id();
+        let second = next_insert_id();
+
+        let (first_prefix, first_counter) = first.split_once('-').unwrap();
+        let (second_prefix, second_counter) = second.split_once('-').unwrap();
+
+        assert_eq!(first_prefix, second_prefix);
+        assert_eq!(first_prefix.len(), 16);
+        assert!(first_counter < second_counter);
+    }
+}
@@ -0,0 +1,347 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use tracing_gcloud_layer::{
+    DefaultGCloudLayerConfigBuilder, GCloudLayer, GoogleWriterConfig, JwtSigner, SignFuture,
+    testing::{FAKE_PROJECT_ID, FakeCloudLogging, Fault},
+};
+use tracing_subscriber::prelude::*;
+
+fn layer(server: &FakeCloudLogging, max_batch: usize) -> GCloudLayer {
+    DefaultGCloudLayerConfigBuilder::default()
+        .log_name("my-service")
+        .logger_credential(server.credential())
+        .logging_endpoint(server.endpoint())
+        .labels([(String::from("env"), String::from("test"))])
+        .config(GoogleWriterConfig {
+            max_batch,
+            ..Default::default()
+        })
+        .build()
+        .unwrap()
+        .build_layer()
+        .unwrap()
+}
+
+/// The service account key of `test_fixtures`, in [`FAKE_PROJECT_ID`] and exchanged for
+/// tokens at `server`.
+fn service_account_key(server: &FakeCloudLogging) -> serde_json::Value {
+    let mut key: serde_json::Value =
+        serde_json::from_slice(include_bytes!("../test_fixtures/service-account-key.json"))
+            .unwrap();
+    key["project_id"] = FAKE_PROJECT_ID.into();
+    key["token_uri"] = server.token_uri().into();
+    key
+}
+
+/// Logs `count` events through `layer`, flushing them when the subscriber is dropped.
+fn log(layer: GCloudLayer, count: usize) {
+    tracing::subscriber::with_default(tracing_subscriber::registry().with(layer), || {
+        for index in 0..count {
+            tracing::info!(index, "event {index}");
+        }
+    });
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_writes_entries() {
+    let server = FakeCloudLogging::start().await.unwrap();
+
+    log(layer(&server, 10), 3);
+
+    let entries = server.entries();
+    assert_eq!(entries.len(), 3);
+    assert_eq!(
+        entries[0]["log_name"],
+        format!("projects/{FAKE_PROJECT_ID}/logs/my-service")
+    );
+    assert_eq!(entries[0]["severity"], "INFO");
+    assert_eq!(entries[0]["labels"]["env"], "test");
+    assert_eq!(entries[2]["json_payload"]["message"], "event 2");
+    assert_eq!(server.write_requests(), 1);
+    assert_eq!(server.token_requests(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_retries_rate_limits_and_server_errors() {
+    let server = FakeCloudLogging::start().await.unwrap();
+    server.fail_token(Fault::Status(503));
+    server.fail_write(Fault::Status(429));
+    server.fail_write(Fault::Status(500));
+
+    log(layer(&server, 10), 1);
+
+    assert_eq!(server.entries().len(), 1);
+    assert_eq!(server.token_requests(), 2);
+    assert_eq!(server.write_requests(), 3);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_partial_errors_keep_valid_entries() {
+    let server = FakeCloudLogging::start().await.unwrap();
+    server.fail_write(Fault::PartialErrors(vec![0]));
+
+    log(layer(&server, 2), 4);
+
+    // only the rejected entry is dropped, and the batch is not retried
+    let messages: Vec<_> = server
+        .entries()
+        .iter()
+        .map(|entry| entry["json_payload"]["message"].clone())
+        .collect();
+    assert_eq!(messages, ["event 1", "event 2", "event 3"]);
+    assert_eq!(server.write_requests(), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_slow_responses() {
+    let server = FakeCloudLogging::start().await.unwrap();
+    server.fail_write(Fault::Delay(Duration::from_millis(300)));
+
+    log(layer(&server, 1), 3);
+
+    let messages: Vec<_> = server
+        .entries()
+        .iter()
+        .map(|entry| entry["json_payload"]["message"].clone())
+        .collect();
+    assert_eq!(messages, ["event 0", "event 1", "event 2"]);
+}
+
+#[tokio::test]
+async fn test_flush_guard_on_current_thread_runtime() {
+    let server = FakeCloudLogging::start().await.unwrap();
+    let (layer, guard) = DefaultGCloudLayerConfigBuilder::default()
+        .log_name("my-service")
+        .logger_credential(server.credential())
+        .logging_endpoint(server.endpoint())
+        .build()
+        .unwrap()
+        .build_layer_with_guard()
+        .unwrap();
+    let default = tracing::subscriber::set_default(tracing_subscriber::registry().with(layer));
+
+    tracing::info!("flushed");
+    guard.flush().await;
+    assert_eq!(server.entries().len(), 1);
+
+    // neither the guard nor the layer can block here, and must not panic
+    tracing::info!("not flushed");
+    drop(guard);
+    drop(default);
+    assert_eq!(server.entries().len(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_writing_resumes_after_invalid_grant() {
+    let server = FakeCloudLogging::start().await.unwrap();
+    let path = std::env::temp_dir().join(format!("fake-credential-{}.json", std::process::id()));
+    std::fs::write(&path, server.credential()).unwrap();
+    server.fail_token(Fault::InvalidGrant);
+
+    let (layer, guard) = DefaultGCloudLayerConfigBuilder::default()
+        .log_name("my-service")
+        .logger_credential_file(path.clone())
+        .logging_endpoint(server.endpoint())
+        .config(GoogleWriterConfig {
+            max_batch: 1,
+            fatal_error_cooldown: Duration::from_millis(300),
+            ..Default::default()
+        })
+        .build()
+        .unwrap()
+        .build_layer_with_guard()
+        .unwrap();
+    let _default = tracing::subscriber::set_default(tracing_subscriber::registry().with(layer));
+
+    // the key is rejected, entries are dropped during the cooldown
+    tracing::info!("rejected");
+    guard.flush().await;
+    tracing::info!("dropped");
+    guard.flush().await;
+    assert_eq!(server.token_requests(), 1);
+    assert_eq!(server.write_requests(), 0);
+
+    // the rotated key is picked up once the cooldown ends
+    let mut credential: serde_json::Value = serde_json::from_slice(&server.credential()).unwrap();
+    credential["refresh_token"] = "rotated-refresh-token".into();
+    std::fs::write(&path, credential.to_string()).unwrap();
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    tracing::info!("resumed");
+    guard.flush().await;
+
+    let messages: Vec<_> = server
+        .entries()
+        .iter()
+        .map(|entry| entry["json_payload"]["message"].clone())
+        .collect();
+    assert_eq!(messages, ["resumed"]);
+    assert_eq!(server.token_requests(), 2);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_verify_credentials() {
+    let server = FakeCloudLogging::start().await.unwrap();
+    server.fail_token(Fault::InvalidGrant);
+
+    let config = DefaultGCloudLayerConfigBuilder::default()
+        .log_name("my-service")
+        .logger_credential(server.credential())
+        .logging_endpoint(server.endpoint())
+        .build()
+        .unwrap();
+
+    assert!(config.verify_credentials().await.is_err());
+    assert!(config.verify_credentials().await.is_ok());
+    assert_eq!(server.token_requests(), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_flush_guard() {
+    let server = FakeCloudLogging::start().await.unwrap();
+    let (layer, guard) = DefaultGCloudLayerConfigBuilder::default()
+        .log_name("my-service")
+        .logger_credential(server.credential())
+        .logging_endpoint(server.endpoint())
+        .build()
+        .unwrap()
+        .build_layer_with_guard()
+        .unwrap();
+
+    // the subscriber stays installed, as with `.init()`
+    let _default = tracing::subscriber::set_default(tracing_subscriber::registry().with(layer));
+    tracing::info!(
+        identity = guard.identity(),
+        "authenticating to Google Cloud"
+    );
+    guard.flush().await;
+    let entries = server.entries();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0]["json_payload"]["identity"], "tester@example.com");
+
+    tracing::info!("flushed on drop");
+    drop(guard);
+    assert_eq!(server.entries().len(), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_layer_alone_enables_events() {
+    let server = FakeCloudLogging::start().await.unwrap();
+
+    // without span events, the layer must not report an `OFF` level hint
+    let subscriber = tracing_subscriber::registry().with(layer(&server, 10));
+    tracing::subscriber::with_default(subscriber, || {
+        assert!(tracing::enabled!(tracing::Level::INFO));
+    });
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_self_signed_jwt() {
+    let server = FakeCloudLogging::start().await.unwrap();
+    let layer = DefaultGCloudLayerConfigBuilder::default()
+        .log_name("my-service")
+        .logger_credential(service_account_key(&server).to_string())
+        .logging_endpoint(server.endpoint())
+        .self_signed_jwt(true)
+        .build()
+        .unwrap()
+        .build_layer()
+        .unwrap();
+
+    log(layer, 2);
+
+    // the JWT is sent as the bearer token, without a token exchange
+    assert_eq!(server.entries().len(), 2);
+    assert_eq!(server.token_requests(), 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_jwt_signer() {
+    /// Counts the JWTs it signs; the fake token endpoint does not check signatures.
+    #[derive(Default)]
+    struct CountingSigner(AtomicUsize);
+
+    impl JwtSigner for CountingSigner {
+        fn sign<'a>(&'a self, _message: &'a [u8]) -> SignFuture<'a> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async { Ok(b"signature".to_vec()) })
+        }
+    }
+
+    let server = FakeCloudLogging::start().await.unwrap();
+    let signer = Arc::new(CountingSigner::default());
+    let mut key = service_account_key(&server);
+    key.as_object_mut().unwrap().remove("private_key");
+    let layer = DefaultGCloudLayerConfigBuilder::default()
+        .log_name("my-service")
+        .logger_credential(key.to_string())
+        .logging_endpoint(server.endpoint())
+        .jwt_signer(signer.clone() as Arc<dyn JwtSigner>)
+        .build()
+        .unwrap()
+        .build_layer()
+        .unwrap();
+
+    log(layer, 1);
+
+    assert_eq!(server.entries().len(), 1);
+    assert_eq!(server.token_requests(), 1);
+    assert_eq!(signer.0.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_severity_override() {
+    let server = FakeCloudLogging::start().await.unwrap();
+
+    let subscriber = tracing_subscriber::registry().with(layer(&server, 10));
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(severity = "critical", "disk full");
+        tracing::info!(severity = "DEFAULT", "explicit default");
+        tracing::warn!(severity = "not-a-severity", "invalid override");
+    });
+
+    let entries = server.entries();
+    let severities: Vec<_> = entries.iter().map(|entry| &entry["severity"]).collect();
+    assert_eq!(severities, ["CRITICAL", "DEFAULT", "WARNING"]);
+    assert!(
+        entries
+            .iter()
+            .all(|entry| entry["json_payload"].get("severity").is_none())
+    );
+}
+
+#[cfg(feature = "trace")]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_trace_export() {
+    let server = FakeCloudLogging::start().await.unwrap();
+    let config = DefaultGCloudLayerConfigBuilder::default()
+        .log_name("my-service")
+        .logger_credential(server.credential())
+        .logging_endpoint(server.endpoint())
+        .build()
+        .unwrap();
+    let trace_layer = config
+        .build_trace_layer()
+        .unwrap()
+        .with_endpoint(server.endpoint());
+
+    // dropping the subscriber flushes the exported spans
+    tracing::subscriber::with_default(
+        tracing_subscriber::registry().with(trace_layer.and_then(config.build_layer().unwrap())),
+        || tracing::info_span!("request").in_scope(|| tracing::info!("handled")),
+    );
+
+    let spans = server.spans();
+    assert_eq!(spans.len(), 1);
+    let span_name = spans[0]["name"].as_str().unwrap();
+    assert!(span_name.starts_with(&format!("projects/{FAKE_PROJECT_ID}/traces/")));
+    let entries = server.entries();
+    assert_eq!(entries[0]["span_id"], spans[0]["spanId"]);
+    assert_eq!(entries[0]["trace_sampled"], true);
+}